@@ -0,0 +1,254 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Ident, LitInt, LitStr, Type};
+
+/// Options of `#[command(...)]`.
+struct CommandAttr {
+    /// Model state type.
+    state: Type,
+    /// Method called by `execute`.
+    execute: Ident,
+    /// Command id.
+    id: Option<LitInt>,
+    /// Command name.
+    name: Option<LitStr>,
+    /// Command metadata.
+    meta: CommandMeta,
+}
+
+/// Metadata options shared by `#[command(...)]` and `#[command_set(...)]`.
+#[derive(Default, Clone)]
+pub struct CommandMeta {
+    /// `category = fs`.
+    pub category: Option<Ident>,
+    /// `read_only`.
+    pub read_only: bool,
+}
+
+impl CommandMeta {
+    /// Parse a metadata option, return `false` if `meta` is not one.
+    pub fn parse(&mut self, meta: &syn::meta::ParseNestedMeta) -> syn::Result<bool> {
+        if meta.path.is_ident("category") {
+            self.category = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("read_only") {
+            self.read_only = true;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Overlay options of `other` on self.
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            category: other.category.clone().or_else(|| self.category.clone()),
+            read_only: self.read_only || other.read_only,
+        }
+    }
+
+    /// Expression of the `Category`, e.g. `fs` => `Category::Fs`.
+    pub fn category(&self) -> TokenStream {
+        let category = match &self.category {
+            Some(ident) => {
+                let name = ident.to_string();
+                let mut chars = name.chars();
+                let first = chars.next().map(|c| c.to_ascii_uppercase());
+                format_ident!("{}", first.into_iter().chain(chars).collect::<String>())
+            }
+            None => format_ident!("Other"),
+        };
+        quote!(::km_checker::Category::#category)
+    }
+}
+
+impl CommandAttr {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut state = None;
+        let mut execute = None;
+        let mut id = None;
+        let mut name = None;
+        let mut meta_opts = CommandMeta::default();
+        for attr in input.attrs.iter().filter(|a| a.path().is_ident("command")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("state") {
+                    state = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("execute") {
+                    execute = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("id") {
+                    id = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("name") {
+                    name = Some(meta.value()?.parse()?);
+                } else if !meta_opts.parse(&meta)? {
+                    return Err(meta.error(
+                        "expected `state`, `execute`, `id`, `name`, `category` or `read_only`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        let missing = |name| {
+            syn::Error::new_spanned(
+                &input.ident,
+                format!("missing `#[command({} = ...)]`", name),
+            )
+        };
+        Ok(Self {
+            state: state.ok_or_else(|| missing("state"))?,
+            execute: execute.ok_or_else(|| missing("execute"))?,
+            id,
+            name,
+            meta: meta_opts,
+        })
+    }
+}
+
+/// Encoding and decoding of a set of fields.
+pub struct FieldCodec {
+    /// Statements encoding each field binding into `buf`.
+    pub encode: Vec<TokenStream>,
+    /// Statements decoding each field binding from `buf`.
+    pub decode: Vec<TokenStream>,
+    /// Pattern/constructor of the fields from their bindings, e.g. `{ a: __field0 }`.
+    pub construct: TokenStream,
+}
+
+impl FieldCodec {
+    pub fn new(fields: &Fields) -> Self {
+        let bindings: Vec<_> = (0..fields.len())
+            .map(|i| format_ident!("__field{}", i))
+            .collect();
+        let encode = bindings
+            .iter()
+            .map(|b| quote! { ::km_checker::Encode::encode(#b, &mut buf); })
+            .collect();
+        let decode = bindings
+            .iter()
+            .map(|b| quote! { let #b = ::km_checker::Decode::decode(&mut buf)?; })
+            .collect();
+        let construct = match fields {
+            Fields::Named(named) => {
+                let names = named.named.iter().map(|f| &f.ident);
+                quote!({ #( #names: #bindings ),* })
+            }
+            Fields::Unnamed(_) => quote!(( #( #bindings ),* )),
+            Fields::Unit => quote!(),
+        };
+        Self {
+            encode,
+            decode,
+            construct,
+        }
+    }
+}
+
+pub fn derive_command(input: DeriveInput) -> syn::Result<TokenStream> {
+    let attr = CommandAttr::parse(&input)?;
+    let name = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "`Command` can only be derived for structs",
+        ));
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let state = &attr.state;
+    let execute = &attr.execute;
+    let id = match &attr.id {
+        Some(id) => quote!(#id),
+        // Hashed by km_checker, so derived ids can't drift from `fnv1a`
+        None => {
+            let name = name.to_string();
+            quote!(::km_checker::state::fnv1a(#name.as_bytes()))
+        }
+    };
+    let FieldCodec {
+        encode,
+        decode,
+        construct,
+    } = FieldCodec::new(&data.fields);
+    let command_name = match &attr.name {
+        Some(command_name) => command_name.value(),
+        None => name.to_string(),
+    };
+    let category = attr.meta.category();
+    let read_only = attr.meta.read_only;
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Command id.
+            pub const ID: u64 = #id;
+        }
+
+        impl #impl_generics ::km_checker::CommandId for #name #ty_generics #where_clause {
+            const ID: u64 = #id;
+        }
+
+        impl #impl_generics ::km_checker::Command<#state> for #name #ty_generics #where_clause {
+            fn execute(&self, state: &mut #state) -> isize {
+                Self::#execute(self, state)
+            }
+            fn to_bytes(&self) -> ::std::vec::Vec<u8> {
+                let mut buf = <Self as ::km_checker::CommandId>::ID.to_le_bytes().to_vec();
+                let Self #construct = self;
+                #( #encode )*
+                buf
+            }
+            fn name(&self) -> ::std::string::String {
+                ::std::string::String::from(#command_name)
+            }
+            fn id(&self) -> ::core::option::Option<u64> {
+                ::core::option::Option::Some(<Self as ::km_checker::CommandId>::ID)
+            }
+            fn category(&self) -> ::km_checker::Category {
+                #category
+            }
+            fn read_only(&self) -> bool {
+                #read_only
+            }
+        }
+
+        impl #impl_generics ::km_checker::FromBytes for #name #ty_generics #where_clause {
+            fn from_bytes(buf: &[u8]) -> ::core::option::Option<Self> {
+                let mut buf = buf;
+                #( #decode )*
+                if !buf.is_empty() {
+                    return ::core::option::Option::None;
+                }
+                ::core::option::Option::Some(Self #construct)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn error(input: DeriveInput) -> String {
+        derive_command(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn bad_attributes_are_rejected() {
+        assert_eq!(
+            error(parse_quote! {
+                #[command(execute = run)]
+                struct Nop;
+            }),
+            "missing `#[command(state = ...)]`"
+        );
+        assert!(error(parse_quote! {
+            #[command(state = S, execute = run, kind = 1)]
+            struct Nop;
+        })
+        .starts_with("expected `state`"));
+        assert_eq!(
+            error(parse_quote! {
+                #[command(state = S, execute = run)]
+                enum Nop {}
+            }),
+            "`Command` can only be derived for structs"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use crate::command::{CommandMeta, FieldCodec};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse::Parser, Fields, Ident, ItemEnum, LitInt, Type};
+
+/// Options of `#[command_set(...)]`.
+struct CommandSetAttr {
+    /// Model state type.
+    state: Type,
+    /// Method called by `execute`.
+    execute: Ident,
+    /// Default metadata of all commands.
+    meta: CommandMeta,
+}
+
+impl CommandSetAttr {
+    fn parse(attr: TokenStream, item: &ItemEnum) -> syn::Result<Self> {
+        let mut state = None;
+        let mut execute = None;
+        let mut meta_opts = CommandMeta::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("state") {
+                state = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("execute") {
+                execute = Some(meta.value()?.parse()?);
+            } else if !meta_opts.parse(&meta)? {
+                return Err(meta.error("expected `state`, `execute`, `category` or `read_only`"));
+            }
+            Ok(())
+        });
+        parser.parse2(attr)?;
+        let missing = |name| {
+            syn::Error::new_spanned(
+                &item.ident,
+                format!("missing `#[command_set({} = ...)]`", name),
+            )
+        };
+        Ok(Self {
+            state: state.ok_or_else(|| missing("state"))?,
+            execute: execute.ok_or_else(|| missing("execute"))?,
+            meta: meta_opts,
+        })
+    }
+}
+
+/// Convert a `CamelCase` identifier to `snake_case`.
+fn snake_case(name: &str) -> String {
+    let mut res = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Take the `#[command(...)]` attribute off a variant.
+fn take_variant_attr(
+    attrs: &mut Vec<syn::Attribute>,
+) -> syn::Result<(Option<LitInt>, CommandMeta)> {
+    let mut id = None;
+    let mut meta_opts = CommandMeta::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("command")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+            } else if !meta_opts.parse(&meta)? {
+                return Err(meta.error("expected `id`, `category` or `read_only`"));
+            }
+            Ok(())
+        })?;
+    }
+    attrs.retain(|a| !a.path().is_ident("command"));
+    Ok((id, meta_opts))
+}
+
+pub fn command_set(attr: TokenStream, mut item: ItemEnum) -> syn::Result<TokenStream> {
+    let attr = CommandSetAttr::parse(attr, &item)?;
+    let mut ids = Vec::new();
+    let mut metas = Vec::new();
+    for (index, variant) in item.variants.iter_mut().enumerate() {
+        let (lit, meta) = take_variant_attr(&mut variant.attrs)?;
+        let id = match &lit {
+            Some(lit) => lit.base10_parse::<u64>()?,
+            None => index as u64,
+        };
+        if ids.contains(&id) {
+            let message = format!("duplicate command id {}", id);
+            return Err(match lit {
+                Some(lit) => syn::Error::new_spanned(lit, message),
+                None => syn::Error::new_spanned(&variant.ident, message),
+            });
+        }
+        ids.push(id);
+        metas.push(attr.meta.merge(&meta));
+    }
+    let name = &item.ident;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    let state = &attr.state;
+    let execute = &attr.execute;
+
+    let mut kinds = Vec::new();
+    let mut id_arms = Vec::new();
+    let mut encode_arms = Vec::new();
+    let mut decode_arms = Vec::new();
+    let mut debug_arms = Vec::new();
+    let mut registers = Vec::new();
+    let mut category_arms = Vec::new();
+    let mut read_only_arms = Vec::new();
+    for ((variant, id), meta) in item.variants.iter().zip(&ids).zip(&metas) {
+        let ident = &variant.ident;
+        let kind = snake_case(&ident.to_string());
+        let FieldCodec {
+            encode,
+            decode,
+            construct,
+        } = FieldCodec::new(&variant.fields);
+        let pattern = match &variant.fields {
+            Fields::Unit => quote!(Self::#ident),
+            _ => quote!(Self::#ident #construct),
+        };
+        kinds.push(quote! {
+            ::km_checker::CommandKind { id: #id, name: #kind }
+        });
+        id_arms.push(quote! { #pattern => #id, });
+        let category = meta.category();
+        category_arms.push(quote! { #pattern => #category, });
+        let read_only = meta.read_only;
+        read_only_arms.push(quote! { #pattern => #read_only, });
+        encode_arms.push(quote! {
+            #pattern => {
+                buf.extend(#id.to_le_bytes());
+                #( #encode )*
+            }
+        });
+        decode_arms.push(quote! {
+            #id => {
+                #( #decode )*
+                #pattern
+            }
+        });
+        let fields = variant.fields.iter().enumerate().map(|(i, f)| {
+            let binding = quote::format_ident!("__field{}", i);
+            let sep = if i == 0 { "" } else { ", " };
+            match &f.ident {
+                Some(field) => {
+                    let label = format!("{}{}: ", sep, field);
+                    quote! { write!(f, "{}{:?}", #label, #binding)?; }
+                }
+                None => quote! { write!(f, "{}{:?}", #sep, #binding)?; },
+            }
+        });
+        debug_arms.push(quote! {
+            #pattern => {
+                write!(f, "{}(", #kind)?;
+                #( #fields )*
+                write!(f, ")")
+            }
+        });
+        registers.push(quote! {
+            registry.register_fn(#id, |payload| {
+                Self::decode_payload(#id, payload)
+                    .map(|cmd| ::std::boxed::Box::new(cmd) as ::std::boxed::Box<dyn ::km_checker::Command<#state>>)
+            })?;
+        });
+    }
+
+    Ok(quote! {
+        #item
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// All command kinds of the set.
+            pub const KINDS: &'static [::km_checker::CommandKind] = &[ #( #kinds ),* ];
+
+            /// Command id of self.
+            pub fn id(&self) -> u64 {
+                #[allow(unused_variables)]
+                match self {
+                    #( #id_arms )*
+                }
+            }
+
+            /// Command kind of self.
+            pub fn kind(&self) -> ::km_checker::CommandKind {
+                let id = self.id();
+                *Self::KINDS.iter().find(|k| k.id == id).unwrap()
+            }
+
+            /// Decode a serialized command, the inverse of `to_bytes`.
+            pub fn decode(buf: &[u8]) -> ::core::option::Option<Self> {
+                let (id, payload) = ::km_checker::split_id(buf)?;
+                Self::decode_payload(id, payload)
+            }
+
+            /// Decode the payload of the command with the given id.
+            pub fn decode_payload(id: u64, payload: &[u8]) -> ::core::option::Option<Self> {
+                let mut buf = payload;
+                let cmd = match id {
+                    #( #decode_arms )*
+                    _ => return ::core::option::Option::None,
+                };
+                buf.is_empty().then_some(cmd)
+            }
+
+            /// Register all commands of the set into a registry.
+            ///
+            /// `DuplicateCommand` if an id is already registered.
+            pub fn register(
+                registry: &mut ::km_checker::CommandRegistry<#state>,
+            ) -> ::core::result::Result<(), ::km_checker::Error> {
+                #( #registers )*
+                ::core::result::Result::Ok(())
+            }
+        }
+
+        impl #impl_generics ::km_checker::Command<#state> for #name #ty_generics #where_clause {
+            fn execute(&self, state: &mut #state) -> isize {
+                Self::#execute(self, state)
+            }
+            fn to_bytes(&self) -> ::std::vec::Vec<u8> {
+                let mut buf = ::std::vec::Vec::new();
+                match self {
+                    #( #encode_arms )*
+                }
+                buf
+            }
+            fn name(&self) -> ::std::string::String {
+                ::std::string::String::from(self.kind().name)
+            }
+            fn id(&self) -> ::core::option::Option<u64> {
+                ::core::option::Option::Some(#name::id(self))
+            }
+            #[allow(unused_variables)]
+            fn category(&self) -> ::km_checker::Category {
+                match self {
+                    #( #category_arms )*
+                }
+            }
+            #[allow(unused_variables)]
+            fn read_only(&self) -> bool {
+                match self {
+                    #( #read_only_arms )*
+                }
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Debug for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self {
+                    #( #debug_arms )*
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn error(attr: TokenStream, item: ItemEnum) -> String {
+        command_set(attr, item).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let attr = quote!(state = S, execute = apply);
+        assert_eq!(
+            error(
+                attr.clone(),
+                parse_quote! {
+                    enum Sys {
+                        Open,
+                        #[command(id = 0)]
+                        Close,
+                    }
+                }
+            ),
+            "duplicate command id 0"
+        );
+        // Defaults are variant indexes and may collide with explicit ids.
+        assert_eq!(
+            error(
+                attr,
+                parse_quote! {
+                    enum Sys {
+                        #[command(id = 1)]
+                        Open,
+                        Close,
+                    }
+                }
+            ),
+            "duplicate command id 1"
+        );
+    }
+
+    #[test]
+    fn bad_attributes_are_rejected() {
+        let item: ItemEnum = parse_quote! {
+            enum Sys {
+                Open,
+            }
+        };
+        assert_eq!(
+            error(quote!(state = S), item.clone()),
+            "missing `#[command_set(execute = ...)]`"
+        );
+        assert!(error(quote!(state = S, execute = apply, kind = fs), item)
+            .starts_with("expected `state`"));
+        assert!(error(
+            quote!(state = S, execute = apply),
+            parse_quote! {
+                enum Sys {
+                    #[command(name = "open")]
+                    Open,
+                }
+            }
+        )
+        .starts_with("expected `id`"));
+    }
+}
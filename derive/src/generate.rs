@@ -0,0 +1,162 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Expr, ExprArray, Field, Fields, Path};
+
+/// Generation strategy of a field, from `#[arg(...)]`.
+enum ArgAttr {
+    /// `Generate::generate`.
+    Default,
+    /// `#[arg(addr)]`: virtual address.
+    Addr,
+    /// `#[arg(ptr)]`: null or invalid pointer.
+    Ptr,
+    /// `#[arg(flags = [A, B])]`: combination of flag bits.
+    Flags(ExprArray),
+    /// `#[arg(range = lo..hi)]`: integer in range.
+    Range(Box<Expr>, Box<Expr>),
+    /// `#[arg(with = path)]`: user function `fn(&mut Rng) -> T`.
+    With(Path),
+}
+
+impl ArgAttr {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut res = Self::Default;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("arg")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("addr") {
+                    res = Self::Addr;
+                } else if meta.path.is_ident("ptr") {
+                    res = Self::Ptr;
+                } else if meta.path.is_ident("flags") {
+                    res = Self::Flags(meta.value()?.parse()?);
+                } else if meta.path.is_ident("range") {
+                    match meta.value()?.parse()? {
+                        Expr::Range(syn::ExprRange {
+                            start: Some(start),
+                            limits: syn::RangeLimits::HalfOpen(_),
+                            end: Some(end),
+                            ..
+                        }) => res = Self::Range(start, end),
+                        expr => {
+                            return Err(syn::Error::new_spanned(expr, "expected `lo..hi`"));
+                        }
+                    }
+                } else if meta.path.is_ident("with") {
+                    res = Self::With(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("expected `addr`, `ptr`, `flags`, `range` or `with`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(res)
+    }
+
+    /// Expression generating the field from `rng`.
+    fn expr(&self, field: &Field) -> TokenStream {
+        let ty = &field.ty;
+        match self {
+            Self::Default => quote!(<#ty as ::km_checker::Generate>::generate(rng)),
+            Self::Addr => quote!(rng.addr() as #ty),
+            Self::Ptr => quote!(rng.ptr() as #ty),
+            Self::Flags(flags) => {
+                let flags = flags.elems.iter();
+                quote!(rng.flags(&[ #( (#flags) as u64 ),* ]) as #ty)
+            }
+            Self::Range(lo, hi) => quote!(rng.range((#lo) as i128, (#hi) as i128) as #ty),
+            Self::With(path) => quote!(#path(rng)),
+        }
+    }
+}
+
+/// Constructor of a set of fields from generated values.
+fn construct(fields: &Fields) -> syn::Result<TokenStream> {
+    let values = fields
+        .iter()
+        .map(|f| ArgAttr::parse(f).map(|attr| attr.expr(f)))
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote!({ #( #names: #values ),* })
+        }
+        Fields::Unnamed(_) => quote!(( #( #values ),* )),
+        Fields::Unit => quote!(),
+    })
+}
+
+pub fn derive_generate(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let construct = construct(&data.fields)?;
+            quote!(Self #construct)
+        }
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "`Generate` cannot be derived for enums without variants",
+                ));
+            }
+            let count = data.variants.len() as u64;
+            let arms = data
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let ident = &v.ident;
+                    let i = i as u64;
+                    construct(&v.fields).map(|c| quote!(#i => Self::#ident #c,))
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match rng.below(#count) {
+                    #( #arms )*
+                    _ => unreachable!(),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "`Generate` cannot be derived for unions",
+            ))
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::km_checker::Generate for #name #ty_generics #where_clause {
+            fn generate(rng: &mut ::km_checker::Rng) -> Self {
+                #body
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn empty_enum_is_rejected() {
+        let err = derive_generate(parse_quote!(
+            enum Never {}
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("without variants"));
+    }
+
+    #[test]
+    fn bad_arg_is_rejected() {
+        let err = derive_generate(parse_quote!(
+            struct Args {
+                #[arg(range = 0..)]
+                len: usize,
+            }
+        ))
+        .unwrap_err();
+        assert_eq!(err.to_string(), "expected `lo..hi`");
+    }
+}
@@ -1,82 +1,112 @@
 extern crate proc_macro;
+mod command;
+mod command_set;
+mod generate;
+mod shrink;
+mod state;
+
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields};
+use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(AbstractState)]
+/// Check a state field by field.
+///
+/// `matches`, `update` and `learn` are forwarded to each field, and `diff`
+/// reports the differing paths of each field prefixed with its name. `key`
+/// combines the keys of the compared fields. Field types depending on type
+/// parameters are bound by `AbstractState`.
+///
+/// On enums, different variants never match and `update` takes the variant of
+/// the other state, which requires `Clone`. Fields of the same variant are
+/// checked as struct fields.
+///
+/// Fields accept `#[km(...)]` options:
+///
+/// - `#[km(ignore)]`: neither compared nor updated.
+/// - `#[km(no_update)]`: compared but never updated nor learned.
+/// - `#[km(no_compare)]`: updated but never compared.
+/// - `#[km(compare_with = path)]`: compared with `fn(&T, &T) -> bool`.
+/// - `#[km(tolerance = expr)]`: numbers match if they differ by at most `expr`.
+/// - `#[km(level = strict)]`: check level (`none`, `relaxed` or `strict`) of
+///   the field, overriding the default state level of the checker. The state
+///   level of a command in the `CheckPolicy` caps it.
+///
+/// Fields compared with `compare_with` or `tolerance` are updated with `Clone`
+/// and don't need to implement `AbstractState`. They are left out of `key`.
+#[proc_macro_derive(AbstractState, attributes(km))]
 pub fn derive_abstract_state(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let name = &input.ident;
+    state::derive_abstract_state(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Turn a plain struct into a model command.
+///
+/// Format: `#[command(state = StateType, execute = method, id = 1)]`.
+///
+/// - `execute` is a method `fn(&self, &mut StateType) -> isize` called by
+///   `Command::execute`.
+/// - `id` is optional and defaults to `km_checker::state::fnv1a` of the type
+///   name.
+/// - `name = "..."` is optional and defaults to the type name.
+/// - `category = fs` (see `Category`, lowercase) and `read_only` are optional
+///   command metadata.
+///
+/// Generates `ID`, `CommandId`, `Command` and `FromBytes`. Fields are serialized
+/// with `Encode`/`Decode`. The struct must implement `Debug`.
+#[proc_macro_derive(Command, attributes(command))]
+pub fn derive_command(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    command::derive_command(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    let gen_tokens = match &input.data {
-        Data::Struct(data) => match &data.fields {
-            Fields::Named(fields) => {
-                let matches_impl = fields.named.iter().map(|f| {
-                    let field_name = &f.ident;
-                    quote! {
-                        if !self.#field_name.matches(&other.#field_name) {
-                            return false;
-                        }
-                    }
-                });
-                let update_impl = fields.named.iter().map(|f| {
-                    let field_name = &f.ident;
-                    quote! {
-                        self.#field_name.update(&other.#field_name);
-                    }
-                });
-                quote! {
-                    impl AbstractState for #name {
-                        fn matches(&self, other: &Self) -> bool {
-                            #( #matches_impl )*
-                            true
-                        }
-                        fn update(&mut self, other: &Self) {
-                            #( #update_impl )*
-                        }
-                    }
-                }
-            }
-            Fields::Unnamed(fields) => {
-                let matches_impl = fields.unnamed.iter().enumerate().map(|(i, _)| {
-                    let index = syn::Index::from(i);
-                    quote! {
-                        if !self.#index.matches(&other.#index) {
-                            return false;
-                        }
-                    }
-                });
-                let update_impl = fields.unnamed.iter().enumerate().map(|(i, _)| {
-                    let index = syn::Index::from(i);
-                    quote! {
-                        self.#index.update(&other.#index);
-                    }
-                });
-                quote! {
-                    impl AbstractState for #name {
-                        fn matches(&self, other: &Self) -> bool {
-                            #( #matches_impl )*
-                            true
-                        }
-                        fn update(&mut self, other: &Self) {
-                            #( #update_impl )*
-                        }
-                    }
-                }
-            }
-            Fields::Unit => {
-                quote! {
-                    impl AbstractState for #name {
-                        fn matches(&self, other: &Self) -> bool {
-                            true
-                        }
-                        fn update(&mut self, _other: &Self) {}
-                    }
-                }
-            }
-        },
-        _ => unimplemented!(),
-    };
+/// Declare a set of model commands as an enum.
+///
+/// Format: `#[command_set(state = StateType, execute = method)]`, where `method`
+/// is `fn(&self, &mut StateType) -> isize` implemented on the enum.
+///
+/// Each variant is a command named after the variant in snake case. Its id is
+/// the variant index, or given with `#[command(id = N)]`. `category = fs` and
+/// `read_only` may be given on the enum as defaults, or on a variant with
+/// `#[command(...)]`. Generates `Command`, a pretty `Debug` form such as
+/// `open(path: "/a", flags: 2)`, `id()`, `kind()`, `decode()`, `register()`
+/// and `KINDS`, the list of all command kinds.
+#[proc_macro_attribute]
+pub fn command_set(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as syn::ItemEnum);
+    command_set::command_set(attr.into(), item)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    gen_tokens.into()
+/// Generate random command arguments, biased towards boundary values.
+///
+/// Works on structs and enums (a random variant is chosen). Fields use
+/// `Generate` unless annotated with `#[arg(...)]`:
+///
+/// - `#[arg(addr)]`: page-aligned, misaligned or null address.
+/// - `#[arg(ptr)]`: null or invalid pointer.
+/// - `#[arg(flags = [A, B])]`: combination of flag bits.
+/// - `#[arg(range = lo..hi)]`: integer in `[lo, hi)`.
+/// - `#[arg(with = path)]`: user function `fn(&mut Rng) -> T`.
+#[proc_macro_derive(Generate, attributes(arg))]
+pub fn derive_generate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    generate::derive_generate(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Shrink command arguments field by field, keeping the variant of enums.
+///
+/// Each candidate shrinks a single field with `Shrink`, fields marked
+/// `#[shrink(skip)]` are kept as is. Fields must implement `Clone`.
+#[proc_macro_derive(Shrink, attributes(shrink))]
+pub fn derive_shrink(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    shrink::derive_shrink(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
 }
@@ -0,0 +1,118 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields};
+
+/// Check if a field is marked `#[shrink(skip)]`.
+fn is_skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("shrink")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+/// Statements pushing candidates of `path` with one field shrunk at a time.
+///
+/// Fields are bound to `__field{i}` by a pattern over `path`.
+fn shrink_fields(path: TokenStream, fields: &Fields) -> syn::Result<(TokenStream, TokenStream)> {
+    let bindings: Vec<_> = (0..fields.len())
+        .map(|i| format_ident!("__field{}", i))
+        .collect();
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote!(#path { #( #names: #bindings ),* })
+        }
+        Fields::Unnamed(_) => quote!(#path ( #( #bindings ),* )),
+        Fields::Unit => quote!(#path),
+    };
+    let mut pushes = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if is_skipped(field)? {
+            continue;
+        }
+        let values = bindings.iter().enumerate().map(|(j, b)| {
+            if i == j {
+                quote!(__shrunk)
+            } else {
+                quote!(::core::clone::Clone::clone(#b))
+            }
+        });
+        let construct = match fields {
+            Fields::Named(named) => {
+                let names = named.named.iter().map(|f| &f.ident);
+                quote!(#path { #( #names: #values ),* })
+            }
+            _ => quote!(#path ( #( #values ),* )),
+        };
+        let binding = &bindings[i];
+        pushes.push(quote! {
+            for __shrunk in ::km_checker::Shrink::shrink(#binding) {
+                res.push(#construct);
+            }
+        });
+    }
+    Ok((pattern, quote!( #( #pushes )* )))
+}
+
+pub fn derive_shrink(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let arms = match &input.data {
+        Data::Struct(data) => vec![shrink_fields(quote!(Self), &data.fields)?],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|v| {
+                let ident = &v.ident;
+                shrink_fields(quote!(Self::#ident), &v.fields)
+            })
+            .collect::<syn::Result<_>>()?,
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "`Shrink` cannot be derived for unions",
+            ))
+        }
+    };
+    let arms = arms
+        .into_iter()
+        .map(|(pattern, pushes)| quote!(#pattern => { #pushes }));
+    Ok(quote! {
+        impl #impl_generics ::km_checker::Shrink for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn shrink(&self) -> ::std::vec::Vec<Self> {
+                let mut res = ::std::vec::Vec::new();
+                match self {
+                    #( #arms )*
+                }
+                res
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn bad_attributes_are_rejected() {
+        let err = derive_shrink(parse_quote! {
+            struct Args {
+                #[shrink(ignore)]
+                len: u8,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "expected `skip`");
+    }
+}
@@ -0,0 +1,550 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::{
+    parse_quote, Data, DeriveInput, Expr, Field, Fields, Generics, Ident, Index, Path, Type,
+    Variant,
+};
+
+/// Check if a type mentions one of the given type parameters.
+fn uses_params(ty: &Type, params: &[&Ident]) -> bool {
+    fn walk(tokens: TokenStream, params: &[&Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => params.contains(&&ident),
+            TokenTree::Group(group) => walk(group.stream(), params),
+            _ => false,
+        })
+    }
+    walk(quote!(#ty), params)
+}
+
+/// Add `Field: AbstractState` bounds for field types depending on type
+/// parameters.
+fn add_bounds(generics: &Generics, fields: &[&Type]) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<&Ident> = generics.type_params().map(|p| &p.ident).collect();
+    let bounded: Vec<Type> = fields
+        .iter()
+        .filter(|ty| uses_params(ty, &params))
+        .map(|ty| (*ty).clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for ty in bounded {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::km_checker::state::AbstractState));
+    }
+    generics
+}
+
+/// Options of `#[km(...)]` on a field.
+#[derive(Default)]
+struct KmAttr {
+    /// `ignore`: neither compared nor updated.
+    ignore: bool,
+    /// `no_update`: compared but never updated.
+    no_update: bool,
+    /// `no_compare`: updated but never compared.
+    no_compare: bool,
+    /// `compare_with = path`: compared with `fn(&T, &T) -> bool`.
+    compare_with: Option<Path>,
+    /// `tolerance = expr`: numbers compared up to a difference.
+    tolerance: Option<Expr>,
+    /// `level = strict`: check level of the field.
+    level: Option<Ident>,
+}
+
+impl KmAttr {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut res = Self::default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("km")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("ignore") {
+                    res.ignore = true;
+                } else if meta.path.is_ident("no_update") {
+                    res.no_update = true;
+                } else if meta.path.is_ident("no_compare") {
+                    res.no_compare = true;
+                } else if meta.path.is_ident("compare_with") {
+                    res.compare_with = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("tolerance") {
+                    res.tolerance = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("level") {
+                    let level: Ident = meta.value()?.parse()?;
+                    if !["none", "relaxed", "strict"].contains(&level.to_string().as_str()) {
+                        return Err(syn::Error::new_spanned(
+                            level,
+                            "expected `none`, `relaxed` or `strict`",
+                        ));
+                    }
+                    res.level = Some(level);
+                } else {
+                    return Err(meta.error(
+                        "expected `ignore`, `no_update`, `no_compare`, `compare_with`, `tolerance` or `level`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        if res.compare_with.is_some() && res.tolerance.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`compare_with` and `tolerance` are exclusive",
+            ));
+        }
+        Ok(res)
+    }
+
+    /// Check if the field is compared.
+    fn compares(&self) -> bool {
+        !self.ignore && !self.no_compare
+    }
+
+    /// Check if the field is updated.
+    fn updates(&self) -> bool {
+        !self.ignore && !self.no_update
+    }
+
+    /// Check if the field is compared by `compare_with` or `tolerance`.
+    fn custom_compare(&self) -> bool {
+        self.compare_with.is_some() || self.tolerance.is_some()
+    }
+
+    /// Expression of the field level, e.g. `strict` => `Some(CheckLevel::Strict)`.
+    fn level(&self) -> TokenStream {
+        match &self.level {
+            Some(level) => {
+                let name = level.to_string();
+                let level = format_ident!("{}{}", name[..1].to_uppercase(), &name[1..]);
+                quote!(::core::option::Option::Some(::km_checker::CheckLevel::#level))
+            }
+            None => quote!(::core::option::Option::None),
+        }
+    }
+
+    /// Check if the field type must implement `AbstractState`.
+    fn needs_bound(&self) -> bool {
+        !self.custom_compare() && (self.compares() || self.updates())
+    }
+}
+
+/// A field of the state: its place in self and other, and its state path.
+struct Member {
+    /// Place of the field in self.
+    this: TokenStream,
+    /// Place of the field in other.
+    other: TokenStream,
+    /// Path of the field.
+    path: String,
+    /// Field options.
+    attr: KmAttr,
+}
+
+impl Member {
+    /// Expression checking the field with a custom comparison.
+    fn custom_matches(&self) -> TokenStream {
+        let Member { this, other, .. } = self;
+        match (&self.attr.compare_with, &self.attr.tolerance) {
+            (Some(path), _) => quote!(#path(&#this, &#other)),
+            (_, Some(tolerance)) => quote! {
+                {
+                    let (a, b) = (#this, #other);
+                    (if a > b { a - b } else { b - a }) <= (#tolerance)
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Members of a struct, accessed through `self` and `other`.
+fn struct_members(fields: &Fields) -> syn::Result<Vec<Member>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let (member, path) = match &f.ident {
+                Some(ident) => (quote!(#ident), ident.to_string()),
+                None => {
+                    let index = Index::from(i);
+                    (quote!(#index), i.to_string())
+                }
+            };
+            Ok(Member {
+                this: quote!(self.#member),
+                other: quote!(other.#member),
+                path,
+                attr: KmAttr::parse(f)?,
+            })
+        })
+        .collect()
+}
+
+/// Pattern binding the fields of a variant as `{prefix}{i}`.
+fn variant_pattern(variant: &Variant, prefix: &str) -> TokenStream {
+    let ident = &variant.ident;
+    let bindings = (0..variant.fields.len()).map(|i| format_ident!("{}{}", prefix, i));
+    match &variant.fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote!(Self::#ident { #( #names: #bindings ),* })
+        }
+        Fields::Unnamed(_) => quote!(Self::#ident( #( #bindings ),* )),
+        Fields::Unit => quote!(Self::#ident),
+    }
+}
+
+/// Members of a variant, bound by `variant_pattern`.
+fn variant_members(variant: &Variant) -> syn::Result<Vec<Member>> {
+    variant
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let this = format_ident!("__self_{}", i);
+            let other = format_ident!("__other_{}", i);
+            Ok(Member {
+                this: quote!((*#this)),
+                other: quote!((*#other)),
+                path: match &f.ident {
+                    Some(ident) => ident.to_string(),
+                    None => i.to_string(),
+                },
+                attr: KmAttr::parse(f)?,
+            })
+        })
+        .collect()
+}
+
+/// Bodies of the `AbstractState` methods.
+struct Bodies {
+    matches: TokenStream,
+    update: TokenStream,
+    diff: TokenStream,
+    level: TokenStream,
+    learn: TokenStream,
+    /// Keys of the members, separated by commas.
+    key: TokenStream,
+}
+
+/// Statements checking, updating, diffing, learning members and finding their
+/// level, and their keys.
+fn member_bodies(members: &[Member]) -> Bodies {
+    let compared = members.iter().filter(|m| m.attr.compares());
+    let matches = compared.clone().map(|m| {
+        let Member { this, other, .. } = m;
+        if m.attr.custom_compare() {
+            let matches = m.custom_matches();
+            quote! {
+                if !#matches {
+                    return false;
+                }
+            }
+        } else {
+            quote! {
+                if !::km_checker::state::AbstractState::matches(&#this, &#other) {
+                    return false;
+                }
+            }
+        }
+    });
+    let update = members.iter().filter(|m| m.attr.updates()).map(|m| {
+        let Member { this, other, .. } = m;
+        if m.attr.custom_compare() {
+            quote! {
+                #this = ::core::clone::Clone::clone(&#other);
+            }
+        } else {
+            quote! {
+                ::km_checker::state::AbstractState::update(&mut #this, &#other);
+            }
+        }
+    });
+    let diff = compared.clone().map(|m| {
+        let Member {
+            this, other, path, ..
+        } = m;
+        if m.attr.custom_compare() {
+            let matches = m.custom_matches();
+            quote! {
+                if !#matches {
+                    diffs.push(::std::string::String::from(#path));
+                }
+            }
+        } else {
+            quote! {
+                diffs.extend(
+                    ::km_checker::state::AbstractState::diff(&#this, &#other)
+                        .iter()
+                        .map(|p| ::km_checker::state::join_path(#path, p)),
+                );
+            }
+        }
+    });
+    // Custom compared fields may match loosely, so they have no key.
+    let key = compared
+        .clone()
+        .filter(|m| !m.attr.custom_compare())
+        .map(|m| {
+            let this = &m.this;
+            quote!(::km_checker::state::AbstractState::key(&#this))
+        });
+    // Levels of nested parts take precedence over the level of the field.
+    let level = compared.map(|m| {
+        let Member { this, path, .. } = m;
+        let field_level = m.attr.level();
+        let level = if m.attr.custom_compare() {
+            field_level
+        } else {
+            quote! {
+                ::km_checker::state::AbstractState::level(&#this, rest).or(#field_level)
+            }
+        };
+        quote! {
+            if let ::core::option::Option::Some(rest) = ::km_checker::state::strip_path(path, #path) {
+                return #level;
+            }
+        }
+    });
+    // Custom compared fields need not implement `AbstractState`.
+    let learn = members
+        .iter()
+        .filter(|m| m.attr.updates() && !m.attr.custom_compare())
+        .map(|m| {
+            let Member { this, other, .. } = m;
+            quote! {
+                ::km_checker::state::AbstractState::learn(&mut #this, &#other);
+            }
+        });
+    Bodies {
+        matches: quote!( #( #matches )* true ),
+        update: quote!( #( #update )* ),
+        diff: quote! {
+            let mut diffs = ::std::vec::Vec::new();
+            #( #diff )*
+            diffs
+        },
+        level: quote!( #( #level )* ::core::option::Option::None ),
+        learn: quote!( #( #learn )* ),
+        key: quote!( #( #key ),* ),
+    }
+}
+
+/// Types of the fields that must implement `AbstractState`.
+fn bound_types<'a>(fields: impl Iterator<Item = &'a Field>) -> syn::Result<Vec<&'a Type>> {
+    let mut types = Vec::new();
+    for field in fields {
+        if KmAttr::parse(field)?.needs_bound() {
+            types.push(&field.ty);
+        }
+    }
+    Ok(types)
+}
+
+pub fn derive_abstract_state(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (types, bodies) = match &input.data {
+        Data::Struct(data) => {
+            let mut bodies = member_bodies(&struct_members(&data.fields)?);
+            let keys = bodies.key;
+            bodies.key = quote!(::km_checker::state::ordered_key([#keys]));
+            (bound_types(data.fields.iter())?, bodies)
+        }
+        Data::Enum(data) => {
+            let mut matches_arms = Vec::new();
+            let mut update_arms = Vec::new();
+            let mut diff_arms = Vec::new();
+            let mut level_arms = Vec::new();
+            let mut learn_arms = Vec::new();
+            let mut key_arms = Vec::new();
+            for (index, variant) in data.variants.iter().enumerate() {
+                let this = variant_pattern(variant, "__self_");
+                let other = variant_pattern(variant, "__other_");
+                let Bodies {
+                    matches,
+                    update,
+                    diff,
+                    level,
+                    learn,
+                    key,
+                } = member_bodies(&variant_members(variant)?);
+                let index = index as u64;
+                matches_arms.push(quote!((#this, #other) => { #matches }));
+                update_arms.push(quote!((#this, #other) => { #update }));
+                diff_arms.push(quote!((#this, #other) => { #diff }));
+                level_arms.push(quote!(#this => { #level }));
+                learn_arms.push(quote!((#this, #other) => { #learn }));
+                key_arms.push(quote! {
+                    #this => ::km_checker::state::ordered_key([#index, #key])
+                });
+            }
+            let bodies = Bodies {
+                // Different variants never match.
+                matches: quote! {
+                    match (self, other) {
+                        #( #matches_arms )*
+                        _ => false,
+                    }
+                },
+                // Take the variant of other when they differ.
+                update: quote! {
+                    match (self, other) {
+                        #( #update_arms )*
+                        (this, other) => *this = ::core::clone::Clone::clone(other),
+                    }
+                },
+                diff: quote! {
+                    match (self, other) {
+                        #( #diff_arms )*
+                        _ => ::std::vec![::std::string::String::new()],
+                    }
+                },
+                level: quote! {
+                    match self {
+                        #( #level_arms )*
+                        _ => ::core::option::Option::None,
+                    }
+                },
+                learn: quote! {
+                    match (self, other) {
+                        #( #learn_arms )*
+                        _ => {}
+                    }
+                },
+                // Variants are told apart by their index.
+                key: quote! {
+                    match self {
+                        #( #key_arms, )*
+                    }
+                },
+            };
+            (
+                bound_types(data.variants.iter().flat_map(|v| v.fields.iter()))?,
+                bodies,
+            )
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "`AbstractState` cannot be derived for unions",
+            ))
+        }
+    };
+    let mut generics = add_bounds(&input.generics, &types);
+    if let Data::Enum(_) = &input.data {
+        let (_, ty_generics, _) = input.generics.split_for_impl();
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#name #ty_generics: ::core::clone::Clone));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let Bodies {
+        matches,
+        update,
+        diff,
+        level,
+        learn,
+        key,
+    } = bodies;
+    Ok(quote! {
+        impl #impl_generics ::km_checker::state::AbstractState for #name #ty_generics #where_clause {
+            #[allow(unused_variables, unreachable_patterns)]
+            fn matches(&self, other: &Self) -> bool {
+                #matches
+            }
+            #[allow(unused_variables, unreachable_patterns)]
+            fn update(&mut self, other: &Self) {
+                #update
+            }
+            #[allow(unused_variables, unused_mut, unreachable_patterns)]
+            fn diff(&self, other: &Self) -> ::std::vec::Vec<::std::string::String> {
+                #diff
+            }
+            #[allow(unused_variables, unreachable_patterns)]
+            fn level(&self, path: &str) -> ::core::option::Option<::km_checker::CheckLevel> {
+                #level
+            }
+            #[allow(unused_variables, unreachable_patterns)]
+            fn learn(&mut self, other: &Self) {
+                #learn
+            }
+            #[allow(unused_variables)]
+            fn key(&self) -> u64 {
+                #key
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_fields_using_params_are_bound() {
+        let input: DeriveInput = parse_quote! {
+            struct Table<T, K: Ord> {
+                rows: ValueList<T>,
+                index: ValueMap<K, Value<u8>>,
+                count: u32,
+            }
+        };
+        let Data::Struct(data) = &input.data else {
+            unreachable!()
+        };
+        let types: Vec<&Type> = data.fields.iter().map(|f| &f.ty).collect();
+        let generics = add_bounds(&input.generics, &types);
+        let bounds: Vec<String> = generics
+            .where_clause
+            .unwrap()
+            .predicates
+            .iter()
+            .map(|p| quote!(#p).to_string())
+            .collect();
+        assert_eq!(bounds.len(), 2);
+        assert!(bounds[0].starts_with("ValueList < T >"));
+        assert!(bounds[1].starts_with("ValueMap < K , Value < u8 > >"));
+    }
+
+    #[test]
+    fn unions_are_rejected() {
+        let err = derive_abstract_state(parse_quote! {
+            union Bits {
+                a: u32,
+                b: f32,
+            }
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("unions"));
+    }
+
+    #[test]
+    fn bad_attributes_are_rejected() {
+        let error = |input: DeriveInput| derive_abstract_state(input).unwrap_err().to_string();
+        assert!(error(parse_quote! {
+            struct S {
+                #[km(skip)]
+                a: u32,
+            }
+        })
+        .starts_with("expected `ignore`"));
+        assert_eq!(
+            error(parse_quote! {
+                struct S {
+                    #[km(level = loud)]
+                    a: u32,
+                }
+            }),
+            "expected `none`, `relaxed` or `strict`"
+        );
+        assert_eq!(
+            error(parse_quote! {
+                struct S {
+                    #[km(compare_with = eq, tolerance = 1)]
+                    a: u32,
+                }
+            }),
+            "`compare_with` and `tolerance` are exclusive"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    state::{fingerprint, index_end},
+    Mismatch, RetvClass,
+};
+use core::fmt::{Display, Formatter};
+use std::collections::BTreeMap;
+
+/// Signature of a mismatch, shared by mismatches of the same underlying bug.
+///
+/// Made of the command name, the classes of both return values and the
+/// structural location of the state difference, i.e. differing paths with
+/// indices and keys replaced by `[*]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Signature {
+    /// Name of the command.
+    pub command: String,
+    /// Return value class of the model.
+    pub model_retv: RetvClass,
+    /// Return value class of the target.
+    pub target_retv: RetvClass,
+    /// Sorted normalized paths of the state difference.
+    pub paths: Vec<String>,
+}
+
+impl Signature {
+    /// Stable identifier of the signature, e.g. to name files.
+    pub fn id(&self) -> u64 {
+        fingerprint(self)
+    }
+}
+
+impl From<&Mismatch> for Signature {
+    fn from(mismatch: &Mismatch) -> Self {
+        let mut paths: Vec<String> = mismatch.paths.iter().map(|p| normalize_path(p)).collect();
+        paths.sort();
+        paths.dedup();
+        Self {
+            command: mismatch.command.clone(),
+            model_retv: mismatch.model_retv.into(),
+            target_retv: mismatch.target_retv.into(),
+            paths,
+        }
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} {:?}:{:?} [{}]",
+            self.command,
+            self.model_retv,
+            self.target_retv,
+            self.paths.join(", ")
+        )
+    }
+}
+
+/// Replace indices and keys of a state path with `*`, e.g. `files[3].size`
+/// becomes `files[*].size`.
+pub fn normalize_path(path: &str) -> String {
+    let mut res = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find('[') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = index_end(rest) else {
+            break;
+        };
+        res.push_str("[*]");
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Mismatches sharing a signature.
+#[derive(Debug, Clone)]
+pub struct Bucket<C> {
+    /// Number of mismatches.
+    pub count: usize,
+    /// Shortest known command sequence reproducing the mismatch.
+    pub reproducer: Vec<C>,
+}
+
+/// Mismatches grouped by signature, with one reproducer each.
+///
+/// Displayed as one line per bucket (count and signature) followed by its
+/// reproducer.
+#[derive(Debug, Clone)]
+pub struct Buckets<C> {
+    buckets: BTreeMap<Signature, Bucket<C>>,
+}
+
+impl<C> Default for Buckets<C> {
+    fn default() -> Self {
+        Self {
+            buckets: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C> Buckets<C> {
+    /// Create an empty set of buckets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a mismatch and the command sequence that led to it.
+    ///
+    /// The sequence is minimized with `minimize` (e.g. with `shrink_sequence`)
+    /// if the signature is new or the sequence is shorter than the reproducer
+    /// of its bucket, which it then replaces. Return `true` if the signature
+    /// is new.
+    pub fn insert<F>(&mut self, signature: Signature, reproducer: Vec<C>, minimize: F) -> bool
+    where
+        F: FnOnce(Vec<C>) -> Vec<C>,
+    {
+        match self.buckets.get_mut(&signature) {
+            Some(bucket) => {
+                bucket.count += 1;
+                if reproducer.len() < bucket.reproducer.len() {
+                    bucket.reproducer = minimize(reproducer);
+                }
+                false
+            }
+            None => {
+                let bucket = Bucket {
+                    count: 1,
+                    reproducer: minimize(reproducer),
+                };
+                self.buckets.insert(signature, bucket);
+                true
+            }
+        }
+    }
+
+    /// Get the bucket of a signature.
+    pub fn get(&self, signature: &Signature) -> Option<&Bucket<C>> {
+        self.buckets.get(signature)
+    }
+
+    /// Number of unique signatures.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Check if no mismatch has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Iterate over signatures and their buckets.
+    pub fn iter(&self) -> impl Iterator<Item = (&Signature, &Bucket<C>)> {
+        self.buckets.iter()
+    }
+
+    /// Iterate over signatures and their buckets, e.g. to minimize
+    /// reproducers afterwards.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&Signature, &mut Bucket<C>)> {
+        self.buckets.iter_mut()
+    }
+
+    /// Merge buckets of another run.
+    ///
+    /// Counts are added and the shorter reproducer is kept, reproducers are
+    /// not minimized again.
+    pub fn merge(&mut self, other: Self) {
+        for (signature, bucket) in other.buckets {
+            match self.buckets.get_mut(&signature) {
+                Some(own) => {
+                    own.count += bucket.count;
+                    if bucket.reproducer.len() < own.reproducer.len() {
+                        own.reproducer = bucket.reproducer;
+                    }
+                }
+                None => {
+                    self.buckets.insert(signature, bucket);
+                }
+            }
+        }
+    }
+}
+
+impl<C> Display for Buckets<C>
+where
+    C: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        for (signature, bucket) in &self.buckets {
+            writeln!(f, "{:>6} {}", bucket.count, signature)?;
+            for command in &bucket.reproducer {
+                writeln!(f, "       {:?}", command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_indices_and_keys() {
+        assert_eq!(normalize_path("files[3].size"), "files[*].size");
+        assert_eq!(normalize_path(r#"map["a]b"][0].x"#), "map[*][*].x");
+        assert_eq!(normalize_path("m[[1, 2]]"), "m[*]");
+        assert_eq!(normalize_path("size"), "size");
+    }
+
+    #[test]
+    fn reproducers_are_minimized() {
+        let signature = Signature {
+            command: "mmap".to_string(),
+            model_retv: RetvClass::Error(-12),
+            target_retv: RetvClass::Zero,
+            paths: Vec::new(),
+        };
+        let mut buckets = Buckets::new();
+        let minimize = |mut t: Vec<u32>| {
+            t.retain(|&c| c != 0);
+            t
+        };
+        assert!(buckets.insert(signature.clone(), vec![0, 1, 2, 0, 5], minimize));
+        // A shorter sequence replaces the reproducer only once minimized.
+        assert!(!buckets.insert(signature.clone(), vec![0, 3], minimize));
+        assert!(!buckets.insert(signature.clone(), vec![4, 5, 6], minimize));
+        let bucket = buckets.get(&signature).unwrap();
+        assert_eq!(bucket.count, 3);
+        assert_eq!(bucket.reproducer, vec![3]);
+    }
+}
@@ -0,0 +1,966 @@
+use crate::{
+    port::TestPort, shrink_sequence, AbstractState, Buckets, CheckLevel, CheckPolicy, Checker,
+    Command, CommandRegistry, Commander, Coverage, CoveragePoint, Decode, Encode, Error, Feedback,
+    Printer, ReplayCommander, Rng, Shrink, Signature, Suppressions,
+};
+use core::fmt::{Debug, Display, Formatter};
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::{BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Commander that records the commands yielded by another commander.
+struct TraceCommander<C> {
+    /// Inner command generator.
+    inner: C,
+    /// Serialized commands, see `Command::to_bytes`.
+    trace: Vec<Vec<u8>>,
+    /// Debug strings of the commands.
+    commands: Vec<String>,
+    /// Corpus receiving traces that reach new coverage.
+    corpus: Option<Corpus>,
+}
+
+impl<C, S> Commander<S> for TraceCommander<C>
+where
+    C: Commander<S>,
+    S: AbstractState + Debug,
+{
+    fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        let command = self.inner.command(state)?;
+        self.trace.push(command.to_bytes());
+        self.commands.push(format!("{:?}", command));
+        Ok(command)
+    }
+
+    fn feedback(&mut self, feedback: &Feedback<S>) {
+        if let Some(corpus) = &self.corpus {
+            corpus.add(Coverage::point_of(feedback), &self.trace);
+        }
+        self.inner.feedback(feedback);
+    }
+}
+
+/// Traces that reached new coverage, shared by the sessions of a campaign.
+///
+/// Clones are handles to the same corpus, so commanders can draw from it
+/// while it grows, see `CorpusCommander`.
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    inner: Arc<Mutex<CorpusInner>>,
+}
+
+#[derive(Debug, Default)]
+struct CorpusInner {
+    /// Coverage points reached by all sessions.
+    coverage: Coverage,
+    /// Traces, each reaching a new point with its last command.
+    traces: Vec<Vec<Vec<u8>>>,
+}
+
+impl Corpus {
+    /// Create an empty corpus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a coverage point, adding the trace if it is new.
+    fn add(&self, point: CoveragePoint, trace: &[Vec<u8>]) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.coverage.insert(point) {
+            inner.traces.push(trace.to_vec());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Number of traces.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().traces.len()
+    }
+
+    /// Check if the corpus has no trace.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get a trace by index.
+    pub fn get(&self, index: usize) -> Option<Vec<Vec<u8>>> {
+        self.inner.lock().unwrap().traces.get(index).cloned()
+    }
+
+    /// Copy of the coverage reached by all sessions.
+    pub fn coverage(&self) -> Coverage {
+        self.inner.lock().unwrap().coverage.clone()
+    }
+}
+
+/// Commander that replays a trace of a corpus, then yields the commands of
+/// another commander.
+///
+/// Sessions of a campaign created with it start from coverage reached by
+/// earlier sessions instead of the initial state.
+pub struct CorpusCommander<C, S>
+where
+    S: AbstractState,
+{
+    /// Commands of the trace left to replay.
+    prefix: VecDeque<Box<dyn Command<S>>>,
+    /// Commander taking over after the trace.
+    inner: C,
+}
+
+impl<C, S> CorpusCommander<C, S>
+where
+    S: AbstractState,
+{
+    /// Replay a trace of the corpus chosen by `seed`, decoded with `registry`.
+    ///
+    /// Nothing is replayed if the corpus is empty or the trace can't be decoded.
+    pub fn new(inner: C, corpus: &Corpus, registry: &CommandRegistry<S>, seed: u64) -> Self {
+        let index = Rng::new(seed).below(corpus.len() as u64) as usize;
+        let prefix = corpus
+            .get(index)
+            .and_then(|trace| registry.decode_all(&trace).ok())
+            .unwrap_or_default();
+        Self {
+            prefix: prefix.into(),
+            inner,
+        }
+    }
+
+    /// Number of commands of the trace left to replay.
+    pub fn remaining(&self) -> usize {
+        self.prefix.len()
+    }
+}
+
+impl<C, S> Commander<S> for CorpusCommander<C, S>
+where
+    C: Commander<S>,
+    S: AbstractState,
+{
+    fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        match self.prefix.pop_front() {
+            Some(command) => Ok(command),
+            None => self.inner.command(state),
+        }
+    }
+
+    fn feedback(&mut self, feedback: &Feedback<S>) {
+        self.inner.feedback(feedback);
+    }
+}
+
+/// Result of checking a seed.
+struct SeedOutcome {
+    /// Checked seed.
+    seed: u64,
+    /// Number of mismatches of expected failures.
+    expected_mismatches: usize,
+    /// Number of suppressed mismatches.
+    suppressed: usize,
+    /// Failure, if the check failed.
+    failure: Option<CampaignFailure>,
+    /// Debug strings of the executed commands.
+    commands: Vec<String>,
+}
+
+/// A seed that failed the check.
+#[derive(Debug, Clone)]
+pub struct CampaignFailure {
+    /// Seed of the commander.
+    pub seed: u64,
+    /// Check error.
+    pub error: Error,
+    /// Signature of the mismatch, `None` if the error is not a mismatch.
+    pub signature: Option<Signature>,
+    /// Serialized commands up to the failing one.
+    pub trace: Vec<Vec<u8>>,
+}
+
+/// Aggregated result of a campaign.
+#[derive(Debug, Clone, Default)]
+pub struct CampaignReport {
+    /// Number of checked seeds, including previous sessions.
+    pub runs: u64,
+    /// Number of failed seeds, including previous sessions.
+    pub failed: u64,
+    /// Number of mismatches of expected failures.
+    pub expected_mismatches: usize,
+    /// Number of suppressed mismatches.
+    pub suppressed: usize,
+    /// Failures of this session.
+    pub failures: Vec<CampaignFailure>,
+    /// Mismatches of this session grouped by signature, with the shortest
+    /// failing trace of each.
+    pub buckets: Buckets<Vec<u8>>,
+    /// Whether the reproducers of `buckets` are minimized. Campaigns record
+    /// failing traces as they are, see `Campaign::minimize`.
+    pub minimized: bool,
+}
+
+impl Display for CampaignReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "Runs: {}, failed: {}", self.runs, self.failed)?;
+        writeln!(
+            f,
+            "Expected failures: {}, suppressed: {}",
+            self.expected_mismatches, self.suppressed
+        )?;
+        let reproducers = if self.minimized {
+            "minimized"
+        } else {
+            "not minimized"
+        };
+        writeln!(
+            f,
+            "Unique mismatches: {} (reproducers {})",
+            self.buckets.len(),
+            reproducers
+        )?;
+        for (signature, bucket) in self.buckets.iter() {
+            writeln!(
+                f,
+                "{:>6} {} ({} commands)",
+                bucket.count,
+                signature,
+                bucket.reproducer.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a `Checker` on many seeds, each with a fresh target.
+///
+/// With a directory set, every failing trace is written to
+/// `<dir>/<seed>.trace` (see `load_trace`) along with a readable
+/// `<dir>/<seed>.txt`, and progress is saved after each seed so that a
+/// campaign started again on the same directory resumes where it left off.
+///
+/// A resumed campaign counts the runs and failures of previous sessions, but
+/// its report only holds the failures and buckets of the current session.
+/// Those of previous sessions are left in the directory, see `load_trace`.
+pub struct Campaign {
+    /// Seeds to check.
+    seeds: Range<u64>,
+    /// Number of commands checked per seed.
+    rounds: usize,
+    /// Check level of return values.
+    retv_level: CheckLevel,
+    /// Check level of states.
+    state_level: CheckLevel,
+    /// Per-command check levels and expected failures.
+    policy: CheckPolicy,
+    /// Known issues.
+    suppressions: Suppressions,
+    /// Directory of traces and progress.
+    dir: Option<PathBuf>,
+    /// Corpus shared by the sessions.
+    corpus: Option<Corpus>,
+}
+
+impl Campaign {
+    /// Construct a campaign checking `rounds` commands for each seed.
+    ///
+    /// Check levels default to `Strict`.
+    pub fn new(seeds: Range<u64>, rounds: usize) -> Self {
+        Self {
+            seeds,
+            rounds,
+            retv_level: CheckLevel::Strict,
+            state_level: CheckLevel::Strict,
+            policy: CheckPolicy::new(),
+            suppressions: Suppressions::new(),
+            dir: None,
+            corpus: None,
+        }
+    }
+
+    /// Set check levels of return values and states.
+    ///
+    /// Only `Strict` mismatches are reported as failures.
+    pub fn check_levels(mut self, retv_level: CheckLevel, state_level: CheckLevel) -> Self {
+        self.retv_level = retv_level;
+        self.state_level = state_level;
+        self
+    }
+
+    /// Set per-command check levels and expected failures.
+    pub fn policy(mut self, policy: CheckPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set known issues whose mismatches are reported as warnings.
+    pub fn suppressions(mut self, suppressions: Suppressions) -> Self {
+        self.suppressions = suppressions;
+        self
+    }
+
+    /// Persist failing traces and progress to a directory.
+    pub fn persist(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Share a corpus between the sessions of the campaign.
+    ///
+    /// Each trace reaching a new coverage point is added to the corpus. Wrap
+    /// the commanders in `CorpusCommander` to continue from its traces.
+    pub fn corpus(mut self, corpus: Corpus) -> Self {
+        self.corpus = Some(corpus);
+        self
+    }
+
+    /// Run the campaign.
+    ///
+    /// `commander` creates the commander of a seed, `target` connects to a
+    /// fresh target instance and `printer` creates the printer of a seed.
+    /// Errors of the target factory and of the directory abort the campaign.
+    pub fn run<C, T, P, S, FC, FT, FP>(
+        &self,
+        mut commander: FC,
+        mut target: FT,
+        mut printer: FP,
+        state: S,
+    ) -> Result<CampaignReport, Error>
+    where
+        C: Commander<S>,
+        T: TestPort<S>,
+        P: Printer,
+        S: AbstractState + Debug + Clone,
+        FC: FnMut(u64) -> C,
+        FT: FnMut() -> Result<T, Error>,
+        FP: FnMut(u64) -> P,
+    {
+        let (mut report, mut progress) = self.begin()?;
+        for seed in self.pending(&progress) {
+            let outcome = self.check_seed(
+                seed,
+                commander(seed),
+                target()?,
+                printer(seed),
+                state.clone(),
+            );
+            self.record(&mut report, &mut progress, outcome)?;
+        }
+        report.failures.sort_by_key(|f| f.seed);
+        Ok(report)
+    }
+
+    /// Run the campaign in `workers` parallel threads.
+    ///
+    /// Like `run`, but seeds are distributed among the workers, each checking
+    /// one seed at a time on its own target. `target` receives the index of
+    /// the worker, e.g. to start a separate QEMU instance per worker. Results
+    /// are merged in a single report, and progress in the same directory.
+    pub fn run_parallel<C, T, P, S, FC, FT, FP>(
+        &self,
+        workers: usize,
+        commander: FC,
+        target: FT,
+        printer: FP,
+        state: S,
+    ) -> Result<CampaignReport, Error>
+    where
+        C: Commander<S>,
+        T: TestPort<S>,
+        P: Printer,
+        S: AbstractState + Debug + Clone + Sync,
+        FC: Fn(u64) -> C + Sync,
+        FT: Fn(usize) -> Result<T, Error> + Sync,
+        FP: Fn(u64) -> P + Sync,
+    {
+        let (report, progress) = self.begin()?;
+        let seeds = self.pending(&progress);
+        let shared = Mutex::new((report, progress));
+        let index = AtomicUsize::new(0);
+        let error = Mutex::new(None);
+        let (seeds, shared, index, error) = (&seeds, &shared, &index, &error);
+        let (commander, target, printer, state) = (&commander, &target, &printer, &state);
+        std::thread::scope(|scope| {
+            for worker in 0..workers.max(1) {
+                scope.spawn(move || {
+                    while error.lock().unwrap().is_none() {
+                        let Some(&seed) = seeds.get(index.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        let res = target(worker).and_then(|port| {
+                            let outcome = self.check_seed(
+                                seed,
+                                commander(seed),
+                                port,
+                                printer(seed),
+                                state.clone(),
+                            );
+                            let (report, progress) = &mut *shared.lock().unwrap();
+                            self.record(report, progress, outcome)
+                        });
+                        if let Err(e) = res {
+                            error.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                });
+            }
+        });
+        if let Some(e) = error.lock().unwrap().take() {
+            return Err(e);
+        }
+        let (mut report, _) = shared.lock().unwrap().clone();
+        report.failures.sort_by_key(|f| f.seed);
+        Ok(report)
+    }
+
+    /// Minimize the reproducers of the buckets of a report.
+    ///
+    /// Each reproducer is shrunk with `shrink_sequence`, removing commands and
+    /// then shrinking their arguments, as long as replaying it on a fresh
+    /// target from `target` still fails with the same signature. `decode`
+    /// decodes a serialized command, e.g. the `decode` of a `command_set`,
+    /// and `printer` creates the printer of a replay. Buckets with commands
+    /// that can't be decoded are left as is. Errors of the target factory
+    /// abort the minimization, leaving the remaining buckets as is.
+    pub fn minimize<C, T, P, S, FD, FT, FP>(
+        &self,
+        report: &mut CampaignReport,
+        decode: FD,
+        mut target: FT,
+        mut printer: FP,
+        state: S,
+    ) -> Result<(), Error>
+    where
+        C: Command<S> + Shrink + Clone + 'static,
+        T: TestPort<S>,
+        P: Printer,
+        S: AbstractState + Debug + Clone,
+        FD: Fn(&[u8]) -> Option<C>,
+        FT: FnMut() -> Result<T, Error>,
+        FP: FnMut() -> P,
+    {
+        let mut error = None;
+        for (signature, bucket) in report.buckets.iter_mut() {
+            let Some(commands) = bucket
+                .reproducer
+                .iter()
+                .map(|buf| decode(buf))
+                .collect::<Option<Vec<C>>>()
+            else {
+                continue;
+            };
+            let minimized = shrink_sequence(commands, |candidate| {
+                if error.is_some() {
+                    return false;
+                }
+                let commands = candidate
+                    .iter()
+                    .map(|c| Box::new(c.clone()) as Box<dyn Command<S>>)
+                    .collect();
+                match target() {
+                    Ok(port) => {
+                        self.replay(commands, port, printer(), state.clone())
+                            .as_ref()
+                            == Some(signature)
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        false
+                    }
+                }
+            });
+            bucket.reproducer = minimized.iter().map(|c| c.to_bytes()).collect();
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+        report.minimized = true;
+        Ok(())
+    }
+
+    /// Replay commands on a fresh target, returning the signature of the
+    /// mismatch they fail with.
+    fn replay<T, P, S>(
+        &self,
+        commands: Vec<Box<dyn Command<S>>>,
+        port: T,
+        printer: P,
+        state: S,
+    ) -> Option<Signature>
+    where
+        T: TestPort<S>,
+        P: Printer,
+        S: AbstractState + Debug,
+    {
+        let mut checker = Checker::new(ReplayCommander::new(commands), port, printer, state);
+        checker.set_policy(self.policy.clone());
+        checker.set_suppressions(self.suppressions.clone());
+        let mut result = checker.run_round(self.retv_level, self.state_level);
+        while result.is_ok() && checker.commander().remaining() > 0 {
+            result = checker.run_round(self.retv_level, self.state_level);
+        }
+        result.err().and(checker.mismatch()).map(Signature::from)
+    }
+
+    /// Create the report and progress, resumed from the directory if any.
+    fn begin(&self) -> Result<(CampaignReport, Progress), Error> {
+        let mut report = CampaignReport::default();
+        let mut progress = Progress::default();
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir).map_err(|_| Error::Io)?;
+            if let Some(saved) = load_progress(dir)? {
+                report.runs = saved.runs;
+                report.failed = saved.failed;
+                progress = saved.progress;
+            }
+        }
+        if progress.next < self.seeds.start {
+            progress.next = self.seeds.start;
+            progress.done.retain(|&seed| seed >= self.seeds.start);
+        }
+        Ok((report, progress))
+    }
+
+    /// Seeds left to check.
+    fn pending(&self, progress: &Progress) -> Vec<u64> {
+        (progress.next..self.seeds.end)
+            .filter(|seed| !progress.done.contains(seed))
+            .collect()
+    }
+
+    /// Check a seed on a fresh target.
+    fn check_seed<C, T, P, S>(
+        &self,
+        seed: u64,
+        commander: C,
+        port: T,
+        printer: P,
+        state: S,
+    ) -> SeedOutcome
+    where
+        C: Commander<S>,
+        T: TestPort<S>,
+        P: Printer,
+        S: AbstractState + Debug,
+    {
+        let tracer = TraceCommander {
+            inner: commander,
+            trace: Vec::new(),
+            commands: Vec::new(),
+            corpus: self.corpus.clone(),
+        };
+        let mut checker = Checker::new(tracer, port, printer, state);
+        checker.set_policy(self.policy.clone());
+        checker.set_suppressions(self.suppressions.clone());
+        let mut result = Ok(());
+        for _ in 0..=self.rounds {
+            result = checker.run_round(self.retv_level, self.state_level);
+            if result.is_err() {
+                break;
+            }
+        }
+        let failure = result.err().map(|error| CampaignFailure {
+            seed,
+            error,
+            signature: checker.mismatch().map(Signature::from),
+            trace: checker.commander().trace.clone(),
+        });
+        SeedOutcome {
+            seed,
+            expected_mismatches: checker.expected_mismatches(),
+            suppressed: checker.suppressions().suppressed(),
+            failure,
+            commands: checker.commander().commands.clone(),
+        }
+    }
+
+    /// Add the outcome of a seed to the report, persisting it.
+    fn record(
+        &self,
+        report: &mut CampaignReport,
+        progress: &mut Progress,
+        outcome: SeedOutcome,
+    ) -> Result<(), Error> {
+        report.runs += 1;
+        report.expected_mismatches += outcome.expected_mismatches;
+        report.suppressed += outcome.suppressed;
+        if let Some(failure) = outcome.failure {
+            if let Some(dir) = &self.dir {
+                save_failure(dir, &failure, &outcome.commands)?;
+            }
+            if let Some(signature) = &failure.signature {
+                // Replaying needs to decode the trace, see `minimize`
+                report
+                    .buckets
+                    .insert(signature.clone(), failure.trace.clone(), |t| t);
+            }
+            report.failed += 1;
+            report.failures.push(failure);
+        }
+        progress.complete(outcome.seed);
+        if let Some(dir) = &self.dir {
+            save_progress(dir, report, progress)?;
+        }
+        Ok(())
+    }
+}
+
+/// Load a failing trace written by a campaign.
+///
+/// Decode the commands with `CommandRegistry::decode_all` to replay them.
+pub fn load_trace(path: impl AsRef<Path>) -> Result<Vec<Vec<u8>>, Error> {
+    let data = std::fs::read(path).map_err(|_| Error::Io)?;
+    let mut buf = data.as_slice();
+    let trace = Vec::<Vec<u8>>::decode(&mut buf).ok_or(Error::Parse)?;
+    if !buf.is_empty() {
+        return Err(Error::Parse);
+    }
+    Ok(trace)
+}
+
+/// Write the trace and readable report of a failing seed.
+fn save_failure(dir: &Path, failure: &CampaignFailure, commands: &[String]) -> Result<(), Error> {
+    let mut data = Vec::new();
+    failure.trace.encode(&mut data);
+    std::fs::write(dir.join(format!("{}.trace", failure.seed)), data).map_err(|_| Error::Io)?;
+    let mut text = format!("seed: {}\nerror: {:?}\n", failure.seed, failure.error);
+    if let Some(signature) = &failure.signature {
+        text += &format!("signature: {:016x} {}\n", signature.id(), signature);
+    }
+    for command in commands {
+        text += &format!("{}\n", command);
+    }
+    std::fs::write(dir.join(format!("{}.txt", failure.seed)), text).map_err(|_| Error::Io)
+}
+
+/// Name of the progress file in a campaign directory.
+const PROGRESS_FILE: &str = "progress";
+
+/// Checked seeds: all seeds below `next` and those in `done`.
+#[derive(Debug, Clone, Default)]
+struct Progress {
+    /// First seed not checked yet.
+    next: u64,
+    /// Checked seeds above `next`, when seeds complete out of order.
+    done: BTreeSet<u64>,
+}
+
+impl Progress {
+    /// Mark a seed as checked.
+    fn complete(&mut self, seed: u64) {
+        self.done.insert(seed);
+        while self.done.remove(&self.next) {
+            self.next += 1;
+        }
+    }
+}
+
+/// Progress and counters loaded from a campaign directory.
+struct SavedProgress {
+    progress: Progress,
+    runs: u64,
+    failed: u64,
+}
+
+/// Save the progress and counters of a campaign.
+fn save_progress(dir: &Path, report: &CampaignReport, progress: &Progress) -> Result<(), Error> {
+    let done: Vec<String> = progress.done.iter().map(u64::to_string).collect();
+    let text = format!(
+        "next={}\ndone={}\nruns={}\nfailed={}\n",
+        progress.next,
+        done.join(","),
+        report.runs,
+        report.failed
+    );
+    // Write then rename, so that an interrupted campaign never leaves a
+    // truncated progress file.
+    let tmp = dir.join(format!("{}.tmp", PROGRESS_FILE));
+    std::fs::write(&tmp, text).map_err(|_| Error::Io)?;
+    std::fs::rename(tmp, dir.join(PROGRESS_FILE)).map_err(|_| Error::Io)
+}
+
+/// Load the progress and counters of a campaign, `None` if it never ran.
+fn load_progress(dir: &Path) -> Result<Option<SavedProgress>, Error> {
+    let path = dir.join(PROGRESS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(path).map_err(|_| Error::Io)?;
+    let parse = |value: &str| value.parse().map_err(|_| Error::Parse);
+    let (mut next, mut runs, mut failed) = (None, None, None);
+    let mut done = BTreeSet::new();
+    for line in text.lines() {
+        let (key, value) = line.split_once('=').ok_or(Error::Parse)?;
+        match key {
+            "next" => next = Some(parse(value)?),
+            "done" => {
+                for seed in value.split(',').filter(|s| !s.is_empty()) {
+                    done.insert(parse(seed)?);
+                }
+            }
+            "runs" => runs = Some(parse(value)?),
+            "failed" => failed = Some(parse(value)?),
+            _ => return Err(Error::Parse),
+        }
+    }
+    match (next, runs, failed) {
+        (Some(next), Some(runs), Some(failed)) => Ok(Some(SavedProgress {
+            progress: Progress { next, done },
+            runs,
+            failed,
+        })),
+        _ => Err(Error::Parse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::port::MockTestPort;
+    use crate::state::key_of;
+    use core::cell::Cell;
+
+    /// Counter whose buggy target adds one too many from 10 on.
+    #[derive(Debug, Clone, Default)]
+    struct Counter {
+        value: u32,
+        buggy: bool,
+    }
+
+    impl AbstractState for Counter {
+        fn matches(&self, other: &Self) -> bool {
+            self.value == other.value
+        }
+        fn update(&mut self, other: &Self) {
+            self.value = other.value;
+        }
+        fn key(&self) -> u64 {
+            key_of(&self.value)
+        }
+        fn diff(&self, other: &Self) -> Vec<String> {
+            if self.matches(other) {
+                Vec::new()
+            } else {
+                vec!["value".to_string()]
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Add(u32);
+
+    impl Command<Counter> for Add {
+        fn execute(&self, state: &mut Counter) -> isize {
+            state.value += self.0;
+            if state.buggy && self.0 >= 10 {
+                state.value += 1;
+            }
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            self.0.to_le_bytes().to_vec()
+        }
+    }
+
+    impl Shrink for Add {
+        fn shrink(&self) -> Vec<Self> {
+            self.0.shrink().into_iter().map(Add).collect()
+        }
+    }
+
+    fn decode(buf: &[u8]) -> Option<Add> {
+        Some(Add(u32::from_le_bytes(buf.try_into().ok()?)))
+    }
+
+    /// Commander adding 1, then the seed, then 1 again.
+    struct SeedCommander {
+        seed: u64,
+        count: usize,
+    }
+
+    impl Commander<Counter> for SeedCommander {
+        fn command(&mut self, _state: &Counter) -> Result<Box<dyn Command<Counter>>, Error> {
+            self.count += 1;
+            let n = if self.count == 2 { self.seed as u32 } else { 1 };
+            Ok(Box::new(Add(n)))
+        }
+    }
+
+    fn commander(seed: u64) -> SeedCommander {
+        SeedCommander { seed, count: 0 }
+    }
+
+    fn target() -> Result<MockTestPort<Counter>, Error> {
+        Ok(MockTestPort::new(Counter {
+            value: 0,
+            buggy: true,
+        }))
+    }
+
+    struct Quiet;
+
+    impl Printer for Quiet {
+        fn print(&mut self, _s: &str) {}
+    }
+
+    /// Empty directory for a test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("km-campaign-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn failing_seeds_are_recorded() {
+        let report = Campaign::new(0..20, 3)
+            .run(commander, target, |_| Quiet, Counter::default())
+            .unwrap();
+        assert_eq!((report.runs, report.failed), (20, 10));
+        let seeds: Vec<u64> = report.failures.iter().map(|f| f.seed).collect();
+        assert_eq!(seeds, (10..20).collect::<Vec<_>>());
+        assert_eq!(report.buckets.len(), 1);
+        let (_, bucket) = report.buckets.iter().next().unwrap();
+        assert_eq!(bucket.count, 10);
+        assert_eq!(
+            bucket.reproducer,
+            vec![Add(1).to_bytes(), Add(10).to_bytes()]
+        );
+        assert!(!report.minimized);
+    }
+
+    #[test]
+    fn minimize_shrinks_arguments() {
+        let campaign = Campaign::new(15..16, 3);
+        let mut report = campaign
+            .run(commander, target, |_| Quiet, Counter::default())
+            .unwrap();
+        campaign
+            .minimize(&mut report, decode, target, || Quiet, Counter::default())
+            .unwrap();
+        let (_, bucket) = report.buckets.iter().next().unwrap();
+        assert_eq!(bucket.reproducer, vec![Add(10).to_bytes()]);
+        assert!(report.minimized);
+    }
+
+    #[test]
+    fn traces_are_written_and_replayed() {
+        let dir = TempDir::new("traces");
+        let campaign = Campaign::new(8..12, 3).persist(&dir.0);
+        let report = campaign
+            .run(commander, target, |_| Quiet, Counter::default())
+            .unwrap();
+        for seed in 8..12 {
+            let trace = dir.0.join(format!("{}.trace", seed));
+            let text = dir.0.join(format!("{}.txt", seed));
+            assert_eq!(trace.exists(), seed >= 10);
+            assert_eq!(text.exists(), seed >= 10);
+        }
+        for failure in &report.failures {
+            let trace = load_trace(dir.0.join(format!("{}.trace", failure.seed))).unwrap();
+            assert_eq!(trace, failure.trace);
+            let commands = trace
+                .iter()
+                .map(|buf| Box::new(decode(buf).unwrap()) as Box<dyn Command<_>>)
+                .collect();
+            let signature = campaign.replay(commands, target().unwrap(), Quiet, Counter::default());
+            assert!(signature.is_some());
+            assert_eq!(signature, failure.signature);
+        }
+    }
+
+    #[test]
+    fn interrupted_campaign_resumes() {
+        let dir = TempDir::new("resume");
+        let campaign = Campaign::new(0..20, 3).persist(&dir.0);
+        // The target factory fails on the 6th seed.
+        let targets = Cell::new(0);
+        let res = campaign.run(
+            commander,
+            || {
+                targets.set(targets.get() + 1);
+                if targets.get() > 5 {
+                    return Err(Error::Io);
+                }
+                target()
+            },
+            |_| Quiet,
+            Counter::default(),
+        );
+        assert!(matches!(res, Err(Error::Io)));
+        let seeds = Cell::new(Vec::new());
+        let report = campaign
+            .run(
+                |seed| {
+                    let mut v = seeds.take();
+                    v.push(seed);
+                    seeds.set(v);
+                    commander(seed)
+                },
+                target,
+                |_| Quiet,
+                Counter::default(),
+            )
+            .unwrap();
+        assert_eq!(seeds.take(), (5..20).collect::<Vec<_>>());
+        assert_eq!((report.runs, report.failed), (20, 10));
+        // Nothing is left to run.
+        let report = campaign
+            .run(
+                |_| -> SeedCommander { unreachable!() },
+                target,
+                |_| Quiet,
+                Counter::default(),
+            )
+            .unwrap();
+        assert_eq!((report.runs, report.failed), (20, 10));
+        assert!(report.failures.is_empty());
+    }
+
+    #[test]
+    fn parallel_run_matches_sequential_run() {
+        let sequential_corpus = Corpus::new();
+        let sequential = Campaign::new(0..40, 3)
+            .corpus(sequential_corpus.clone())
+            .run(commander, target, |_| Quiet, Counter::default())
+            .unwrap();
+        let parallel_corpus = Corpus::new();
+        let parallel = Campaign::new(0..40, 3)
+            .corpus(parallel_corpus.clone())
+            .run_parallel(4, commander, |_| target(), |_| Quiet, Counter::default())
+            .unwrap();
+        assert_eq!(
+            (parallel.runs, parallel.failed),
+            (sequential.runs, sequential.failed)
+        );
+        let seeds = |report: &CampaignReport| -> Vec<u64> {
+            report.failures.iter().map(|f| f.seed).collect()
+        };
+        assert_eq!(seeds(&parallel), seeds(&sequential));
+        assert_eq!(parallel.buckets.len(), sequential.buckets.len());
+        for (signature, bucket) in sequential.buckets.iter() {
+            let other = parallel.buckets.get(signature).unwrap();
+            assert_eq!(other.count, bucket.count);
+            assert_eq!(other.reproducer.len(), bucket.reproducer.len());
+        }
+        // Workers share the corpus, each point is added once.
+        assert!(!sequential_corpus.is_empty());
+        assert_eq!(parallel_corpus.len(), sequential_corpus.len());
+        assert_eq!(
+            parallel_corpus.coverage().len(),
+            sequential_corpus.coverage().len()
+        );
+    }
+}
@@ -1,8 +1,14 @@
-use crate::{port::TestPort, AbstractState, Commander, Error, Printer};
+use crate::{
+    command::Feedback, port::TestPort, AbstractState, Command, Commander, Error, Mismatch, Printer,
+    Suppressions,
+};
 use core::fmt::Debug;
+use std::collections::{HashMap, HashSet};
 
 /// Check level (of retv and state).
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Levels are ordered by severity, from `None` to `Strict`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum CheckLevel {
     /// No checking.
     None,
@@ -12,6 +18,67 @@ pub enum CheckLevel {
     Strict,
 }
 
+/// Check levels overridden per command name, and commands expected to fail.
+///
+/// The state level of a command also caps the levels of the state's own
+/// parts (see `AbstractState::level`), so `CheckLevel::None` skips the state
+/// check of the command altogether. Mismatches of an expected failure are
+/// reported but never returned as errors, so a known bug doesn't stop
+/// checking the other commands.
+#[derive(Debug, Clone, Default)]
+pub struct CheckPolicy {
+    /// Retv check levels by command name.
+    retv_levels: HashMap<String, CheckLevel>,
+    /// State check levels by command name.
+    state_levels: HashMap<String, CheckLevel>,
+    /// Names of commands expected to fail.
+    expected_failures: HashSet<String>,
+}
+
+impl CheckPolicy {
+    /// Create a policy without overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the retv check level of a command.
+    pub fn retv_level(&mut self, name: &str, level: CheckLevel) -> &mut Self {
+        self.retv_levels.insert(name.to_string(), level);
+        self
+    }
+
+    /// Override the state check level of a command, capping the levels of
+    /// the state's parts.
+    pub fn state_level(&mut self, name: &str, level: CheckLevel) -> &mut Self {
+        self.state_levels.insert(name.to_string(), level);
+        self
+    }
+
+    /// Mark a command as expected to fail.
+    pub fn expect_failure(&mut self, name: &str) -> &mut Self {
+        self.expected_failures.insert(name.to_string());
+        self
+    }
+
+    /// Check levels of a command, given the default retv and state levels.
+    pub fn levels(
+        &self,
+        name: &str,
+        retv_level: CheckLevel,
+        state_level: CheckLevel,
+    ) -> (CheckLevel, CheckLevel) {
+        (
+            *self.retv_levels.get(name).unwrap_or(&retv_level),
+            *self.state_levels.get(name).unwrap_or(&state_level),
+        )
+    }
+
+    /// Check if a command is expected to fail.
+    pub fn is_expected_failure(&self, name: &str) -> bool {
+        self.expected_failures.contains(name)
+    }
+}
+
 /// Checker execution steps.
 enum CheckStep {
     /// Start of execution.
@@ -50,6 +117,18 @@ where
     step: CheckStep,
     /// Return value of last command.
     retv: isize,
+    /// Last executed command.
+    command: Option<Box<dyn Command<S>>>,
+    /// Return value of last command from target.
+    test_retv: isize,
+    /// Per-command check levels and expected failures.
+    policy: CheckPolicy,
+    /// Number of mismatches of expected failures.
+    expected_mismatches: usize,
+    /// Known issues reported as warnings.
+    suppressions: Suppressions,
+    /// Unsuppressed mismatch of the last command.
+    mismatch: Option<Mismatch>,
 }
 
 impl<C, T, P, S> Checker<C, T, P, S>
@@ -69,11 +148,73 @@ where
             round: 0,
             step: CheckStep::Start,
             retv: 0,
+            command: None,
+            test_retv: 0,
+            policy: CheckPolicy::new(),
+            expected_mismatches: 0,
+            suppressions: Suppressions::new(),
+            mismatch: None,
+        }
+    }
+
+    /// Set per-command check levels and expected failures.
+    pub fn set_policy(&mut self, policy: CheckPolicy) {
+        self.policy = policy;
+    }
+
+    /// Set known issues whose mismatches are reported as warnings.
+    pub fn set_suppressions(&mut self, suppressions: Suppressions) {
+        self.suppressions = suppressions;
+    }
+
+    /// Mismatch of the last command at the given state paths.
+    fn new_mismatch(&self, paths: Vec<String>) -> Mismatch {
+        Mismatch {
+            command: self.command.as_ref().map(|c| c.name()).unwrap_or_default(),
+            model_retv: self.retv,
+            target_retv: self.test_retv,
+            paths,
+        }
+    }
+
+    /// Check if a mismatch of the last command is a known issue, otherwise
+    /// record it.
+    fn suppress(&mut self, paths: Vec<String>) -> bool {
+        let mismatch = self.new_mismatch(paths);
+        if self.suppressions.suppress(&mismatch) {
+            return true;
+        }
+        self.mismatch = Some(mismatch);
+        false
+    }
+
+    /// Check levels of the last command and whether it is expected to fail.
+    fn command_levels(
+        &self,
+        retv_level: CheckLevel,
+        state_level: CheckLevel,
+    ) -> (CheckLevel, CheckLevel, bool) {
+        match &self.command {
+            Some(command) => {
+                let name = command.name();
+                let (retv_level, state_level) = self.policy.levels(&name, retv_level, state_level);
+                (
+                    retv_level,
+                    state_level,
+                    self.policy.is_expected_failure(&name),
+                )
+            }
+            None => (retv_level, state_level, false),
         }
     }
 
     /// Checker can be regarded as a finite state machine. This is the state transition function.
     ///
+    /// `retv_level` and `state_level` apply to commands without an override in
+    /// the check policy. Parts of the state with their own level
+    /// (`AbstractState::level`) use it instead of `state_level`, but never
+    /// above the state level of the policy.
+    ///
     /// State is transited as follows:
     ///
     /// Start -> Init* -> Command -> CheckRetv -> GetState* -> CheckState -> Command -> ...
@@ -94,6 +235,8 @@ where
             }
             CheckStep::Command => {
                 self.round += 1;
+                self.mismatch = None;
+                self.suppressions.next_command();
                 self.printer
                     .print(&format!("\x1b[1;32m[ Round {} ]\x1b[0m", self.round));
                 // Get command from commander.
@@ -103,21 +246,33 @@ where
                 self.retv = command.execute(&mut self.state);
                 // Send command to test port.
                 self.port.send_command(command.as_ref())?;
+                self.command = Some(command);
                 self.step = CheckStep::CheckRetv;
             }
             CheckStep::CheckRetv => {
                 // Get return value of the command from test target and compare with model.
                 let test_retv = self.port.receive_retv();
+                self.test_retv = test_retv;
                 self.printer.print(&format!(
                     "Expected: {:#x}, Got: {:#x}",
                     self.retv, test_retv
                 ));
+                let (retv_level, _, expected) = self.command_levels(retv_level, state_level);
                 if retv_level != CheckLevel::None && test_retv != self.retv {
-                    self.printer.print("\x1b[1;31mReturn value mismatch\x1b[0m");
-                    self.printer.print("State:");
-                    self.printer.print(&format!("{:?}", self.state));
-                    if retv_level == CheckLevel::Strict {
-                        return Err(Error::ReturnValueMismatch);
+                    if expected {
+                        self.printer
+                            .print("\x1b[1;33mReturn value mismatch (expected failure)\x1b[0m");
+                        self.expected_mismatches += 1;
+                    } else if self.suppress(Vec::new()) {
+                        self.printer
+                            .print("\x1b[1;33mReturn value mismatch (suppressed)\x1b[0m");
+                    } else {
+                        self.printer.print("\x1b[1;31mReturn value mismatch\x1b[0m");
+                        self.printer.print("State:");
+                        self.printer.print(&format!("{:?}", self.state));
+                        if retv_level == CheckLevel::Strict {
+                            return Err(Error::ReturnValueMismatch);
+                        }
                     }
                 }
                 // Start retrieving state from target.
@@ -140,14 +295,65 @@ where
             CheckStep::CheckState => {
                 // Finish state retrieval, compare with model.
                 let test_state = self.port.finish_state_retrieval()?;
-                if state_level != CheckLevel::None && !test_state.matches(&self.state) {
-                    self.printer.print("\x1b[1;31mState mismatch\x1b[0m");
-                    self.printer.print("Expected:");
-                    self.printer.print(&format!("{:?}", self.state));
-                    self.printer.print("Got:");
-                    self.printer.print(&format!("{:?}", test_state));
-                    if state_level == CheckLevel::Strict {
-                        return Err(Error::StateMismatch);
+                if let Some(command) = &self.command {
+                    self.commander.feedback(&Feedback {
+                        command: command.as_ref(),
+                        model_retv: self.retv,
+                        target_retv: self.test_retv,
+                        state: &test_state,
+                        model_state: &self.state,
+                    });
+                }
+                // Take values the model can't predict before comparing.
+                self.state.learn(&test_state);
+                let (_, state_level, expected) = self.command_levels(retv_level, state_level);
+                let cap = self
+                    .command
+                    .as_ref()
+                    .and_then(|c| self.policy.state_levels.get(&c.name()).copied());
+                // Fields may have their own level, even if the state is not checked,
+                // but the level of the command in the policy caps them.
+                if !test_state.matches(&self.state) {
+                    // The whole state differs if no part is found.
+                    let mut paths = self.state.diff(&test_state);
+                    if paths.is_empty() {
+                        paths.push(String::new());
+                    }
+                    // The most severe level of the differing fields applies.
+                    let diffs: Vec<(String, CheckLevel)> = paths
+                        .into_iter()
+                        .map(|path| {
+                            let level = self.state.level(&path).unwrap_or(state_level);
+                            (path, cap.map_or(level, |cap| level.min(cap)))
+                        })
+                        .filter(|(_, level)| *level != CheckLevel::None)
+                        .collect();
+                    let state_level = diffs.iter().map(|(_, level)| *level).max();
+                    let paths: Vec<String> = diffs.into_iter().map(|(path, _)| path).collect();
+                    if state_level.is_none() {
+                        // Only unchecked fields differ.
+                    } else if expected {
+                        // Take the target state so that the known bug doesn't cause
+                        // mismatches of the following commands.
+                        self.printer
+                            .print("\x1b[1;33mState mismatch (expected failure)\x1b[0m");
+                        self.state.update(&test_state);
+                        self.expected_mismatches += 1;
+                    } else if self.suppress(paths.clone()) {
+                        self.printer
+                            .print("\x1b[1;33mState mismatch (suppressed)\x1b[0m");
+                        self.state.update(&test_state);
+                    } else {
+                        self.printer.print("\x1b[1;31mState mismatch\x1b[0m");
+                        self.printer
+                            .print(&format!("Differs at: {}", paths.join(", ")));
+                        self.printer.print("Expected:");
+                        self.printer.print(&format!("{:?}", self.state));
+                        self.printer.print("Got:");
+                        self.printer.print(&format!("{:?}", test_state));
+                        if state_level == Some(CheckLevel::Strict) {
+                            return Err(Error::StateMismatch);
+                        }
                     }
                 }
                 self.step = CheckStep::Command;
@@ -156,8 +362,302 @@ where
         Ok(())
     }
 
+    /// Run steps until the checker is ready to execute the next command.
+    ///
+    /// Called on a fresh checker, this retrieves the initial state. Otherwise it
+    /// executes and checks exactly one command.
+    pub fn run_round(
+        &mut self,
+        retv_level: CheckLevel,
+        state_level: CheckLevel,
+    ) -> Result<(), Error> {
+        loop {
+            self.step(retv_level, state_level)?;
+            if let CheckStep::Command = self.step {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Restore model state and round counter, ready to execute the next command.
+    ///
+    /// Used together with a target snapshot to continue from an earlier round.
+    pub fn restore(&mut self, state: S, round: usize) {
+        self.state = state;
+        self.round = round;
+        self.step = CheckStep::Command;
+    }
+
+    /// Reset the checker to start of execution with the given model state.
+    ///
+    /// The initial state will be retrieved from target again.
+    pub fn reset(&mut self, state: S) {
+        self.state = state;
+        self.round = 0;
+        self.step = CheckStep::Start;
+    }
+
     /// Get a reference to the state.
     pub fn state(&self) -> &S {
         &self.state
     }
+
+    /// Get the round counter.
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    /// Get the check policy.
+    pub fn policy(&self) -> &CheckPolicy {
+        &self.policy
+    }
+
+    /// Get the unsuppressed mismatch of the last command, if any.
+    ///
+    /// Mismatches of expected failures and unchecked values are not recorded.
+    pub fn mismatch(&self) -> Option<&Mismatch> {
+        self.mismatch.as_ref()
+    }
+
+    /// Get the suppressions, with the number of mismatches they suppressed.
+    pub fn suppressions(&self) -> &Suppressions {
+        &self.suppressions
+    }
+
+    /// Number of mismatches of commands expected to fail.
+    pub fn expected_mismatches(&self) -> usize {
+        self.expected_mismatches
+    }
+
+    /// Get a reference to the commander.
+    pub fn commander(&self) -> &C {
+        &self.commander
+    }
+
+    /// Get a mutable reference to the commander.
+    pub fn commander_mut(&mut self) -> &mut C {
+        &mut self.commander
+    }
+
+    /// Get a mutable reference to the test port.
+    pub fn port_mut(&mut self) -> &mut T {
+        &mut self.port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{port::MockTestPort, ReplayCommander};
+
+    /// State with a strict field `a`, an unchecked field `b` and a field
+    /// `hidden` missing from `diff`.
+    ///
+    /// `buggy` is the target's own: it is neither compared nor updated, and
+    /// makes commands bump fields twice and `Fail` fail.
+    #[derive(Debug, Clone, Default)]
+    struct Counters {
+        a: u32,
+        b: u32,
+        hidden: u32,
+        buggy: bool,
+    }
+
+    impl AbstractState for Counters {
+        fn matches(&self, other: &Self) -> bool {
+            self.a == other.a && self.b == other.b && self.hidden == other.hidden
+        }
+        fn update(&mut self, other: &Self) {
+            self.a = other.a;
+            self.b = other.b;
+            self.hidden = other.hidden;
+        }
+        fn diff(&self, other: &Self) -> Vec<String> {
+            let mut diffs = Vec::new();
+            if self.a != other.a {
+                diffs.push("a".to_string());
+            }
+            if self.b != other.b {
+                diffs.push("b".to_string());
+            }
+            diffs
+        }
+        fn level(&self, path: &str) -> Option<CheckLevel> {
+            match path {
+                "a" => Some(CheckLevel::Strict),
+                "b" => Some(CheckLevel::None),
+                _ => None,
+            }
+        }
+    }
+
+    /// Command bumping a field.
+    #[derive(Debug)]
+    struct Bump(&'static str);
+
+    impl Command<Counters> for Bump {
+        fn execute(&self, state: &mut Counters) -> isize {
+            let n = if state.buggy { 2 } else { 1 };
+            match self.0 {
+                "a" => state.a += n,
+                "b" => state.b += n,
+                _ => state.hidden += n,
+            }
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+        fn name(&self) -> String {
+            format!("bump_{}", self.0)
+        }
+    }
+
+    /// Command failing on the target only.
+    #[derive(Debug)]
+    struct Fail;
+
+    impl Command<Counters> for Fail {
+        fn execute(&self, state: &mut Counters) -> isize {
+            -(state.buggy as isize)
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    struct Quiet;
+
+    impl Printer for Quiet {
+        fn print(&mut self, _s: &str) {}
+    }
+
+    type TestChecker = Checker<ReplayCommander<Counters>, MockTestPort<Counters>, Quiet, Counters>;
+
+    /// Run a command on the model and a buggy target.
+    fn run(
+        command: impl Command<Counters> + 'static,
+        policy: CheckPolicy,
+        retv_level: CheckLevel,
+        state_level: CheckLevel,
+    ) -> (Result<(), Error>, TestChecker) {
+        let commander = ReplayCommander::new(vec![Box::new(command) as Box<dyn Command<_>>]);
+        let target = Counters {
+            buggy: true,
+            ..Counters::default()
+        };
+        let port = MockTestPort::new(target);
+        let mut checker = Checker::new(commander, port, Quiet, Counters::default());
+        checker.set_policy(policy);
+        checker.run_round(retv_level, state_level).unwrap();
+        let res = checker.run_round(retv_level, state_level);
+        (res, checker)
+    }
+
+    fn check(
+        field: &'static str,
+        state_level: CheckLevel,
+    ) -> (Result<(), Error>, Option<Mismatch>) {
+        let (res, checker) = run(
+            Bump(field),
+            CheckPolicy::new(),
+            CheckLevel::Strict,
+            state_level,
+        );
+        (res, checker.mismatch().cloned())
+    }
+
+    #[test]
+    fn field_level_applies_without_state_level() {
+        let (res, _) = check("a", CheckLevel::None);
+        assert!(matches!(res, Err(Error::StateMismatch)));
+    }
+
+    #[test]
+    fn unchecked_field_passes() {
+        let (res, mismatch) = check("b", CheckLevel::Strict);
+        assert!(res.is_ok());
+        assert!(mismatch.is_none());
+    }
+
+    #[test]
+    fn mismatch_without_diff_uses_command_level() {
+        let (res, _) = check("hidden", CheckLevel::Strict);
+        assert!(matches!(res, Err(Error::StateMismatch)));
+        let (res, mismatch) = check("hidden", CheckLevel::Relaxed);
+        assert!(res.is_ok());
+        assert_eq!(mismatch.unwrap().paths, vec![String::new()]);
+        let (res, mismatch) = check("hidden", CheckLevel::None);
+        assert!(res.is_ok());
+        assert!(mismatch.is_none());
+    }
+
+    #[test]
+    fn policy_caps_field_levels() {
+        let strict = CheckLevel::Strict;
+        // The strict field `a` is relaxed by the command's level.
+        let mut policy = CheckPolicy::new();
+        policy.state_level("bump_a", CheckLevel::Relaxed);
+        let (res, checker) = run(Bump("a"), policy, strict, strict);
+        assert!(res.is_ok());
+        assert_eq!(checker.mismatch().unwrap().paths, vec!["a"]);
+        // The unchecked field `b` stays unchecked under a strict command.
+        let mut policy = CheckPolicy::new();
+        policy.state_level("bump_b", strict);
+        let (res, _) = run(Bump("b"), policy, strict, strict);
+        assert!(res.is_ok());
+        // Other commands keep the field levels.
+        let mut policy = CheckPolicy::new();
+        policy.state_level("bump_b", CheckLevel::None);
+        let (res, _) = run(Bump("a"), policy, strict, CheckLevel::None);
+        assert!(matches!(res, Err(Error::StateMismatch)));
+    }
+
+    #[test]
+    fn unchecked_command_state() {
+        let strict = CheckLevel::Strict;
+        let mut policy = CheckPolicy::new();
+        policy
+            .state_level("bump_a", CheckLevel::None)
+            .state_level("bump_hidden", CheckLevel::None);
+        let (res, checker) = run(Bump("a"), policy.clone(), strict, strict);
+        assert!(res.is_ok());
+        assert!(checker.mismatch().is_none());
+        let (res, checker) = run(Bump("hidden"), policy, strict, strict);
+        assert!(res.is_ok());
+        assert!(checker.mismatch().is_none());
+    }
+
+    #[test]
+    fn relaxed_retv_is_a_warning() {
+        let (res, _) = run(
+            Fail,
+            CheckPolicy::new(),
+            CheckLevel::Strict,
+            CheckLevel::Strict,
+        );
+        assert!(matches!(res, Err(Error::ReturnValueMismatch)));
+        let mut policy = CheckPolicy::new();
+        policy.retv_level("Fail", CheckLevel::Relaxed);
+        let (res, checker) = run(Fail, policy, CheckLevel::Strict, CheckLevel::Strict);
+        assert!(res.is_ok());
+        let mismatch = checker.mismatch().unwrap();
+        assert_eq!((mismatch.model_retv, mismatch.target_retv), (0, -1));
+        assert!(mismatch.paths.is_empty());
+    }
+
+    #[test]
+    fn expected_failure_takes_the_target_state() {
+        let strict = CheckLevel::Strict;
+        let mut policy = CheckPolicy::new();
+        policy.expect_failure("bump_a").expect_failure("Fail");
+        let (res, checker) = run(Bump("a"), policy.clone(), strict, strict);
+        assert!(res.is_ok());
+        assert!(checker.mismatch().is_none());
+        assert_eq!(checker.expected_mismatches(), 1);
+        assert_eq!(checker.state().a, 2);
+        let (res, checker) = run(Fail, policy, strict, strict);
+        assert!(res.is_ok());
+        assert_eq!(checker.expected_mismatches(), 1);
+    }
 }
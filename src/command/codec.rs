@@ -0,0 +1,184 @@
+/// Serialize a command argument into a stable byte encoding.
+///
+/// Integers are little-endian, `usize`/`isize` are always 8 bytes wide, and
+/// variable-length values are prefixed with their length as a `u64`.
+pub trait Encode {
+    /// Append the encoding of self to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// Deserialize a command argument, the inverse of `Encode`.
+pub trait Decode: Sized {
+    /// Decode a value from the front of `buf` and advance it.
+    fn decode(buf: &mut &[u8]) -> Option<Self>;
+}
+
+/// Take `n` bytes from the front of `buf`.
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Some(head)
+}
+
+/// Implements Encode and Decode for integer types
+macro_rules! impl_codec_int {
+    (for $($t:ty),+) => {
+        $(impl Encode for $t {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.extend(self.to_le_bytes());
+            }
+        }
+        impl Decode for $t {
+            fn decode(buf: &mut &[u8]) -> Option<Self> {
+                let bytes = take(buf, core::mem::size_of::<$t>())?;
+                Some(<$t>::from_le_bytes(bytes.try_into().ok()?))
+            }
+        })*
+    }
+}
+
+impl_codec_int!(for u8, i8, u16, i16, u32, i32, u64, i64, u128, i128);
+
+impl Encode for usize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf);
+    }
+}
+
+impl Decode for usize {
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        u64::decode(buf)?.try_into().ok()
+    }
+}
+
+impl Encode for isize {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as i64).encode(buf);
+    }
+}
+
+impl Decode for isize {
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        i64::decode(buf)?.try_into().ok()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        match u8::decode(buf)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf);
+    }
+}
+
+impl Decode for char {
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        char::from_u32(u32::decode(buf)?)
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        buf.extend(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(buf)?;
+        String::from_utf8(take(buf, len)?.to_vec()).ok()
+    }
+}
+
+impl<T> Encode for Vec<T>
+where
+    T: Encode,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        self.iter().for_each(|v| v.encode(buf));
+    }
+}
+
+impl<T> Decode for Vec<T>
+where
+    T: Decode,
+{
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(buf)?;
+        // Every element takes at least one byte, don't trust `len` beyond that.
+        let mut res = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            res.push(T::decode(buf)?);
+        }
+        Some(res)
+    }
+}
+
+impl<T, const N: usize> Encode for [T; N]
+where
+    T: Encode,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.iter().for_each(|v| v.encode(buf));
+    }
+}
+
+impl<T, const N: usize> Decode for [T; N]
+where
+    T: Decode,
+{
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let mut res = Vec::with_capacity(N);
+        for _ in 0..N {
+            res.push(T::decode(buf)?);
+        }
+        res.try_into().ok()
+    }
+}
+
+impl<T> Encode for Option<T>
+where
+    T: Encode,
+{
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Some(v) => {
+                buf.push(1);
+                v.encode(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+}
+
+impl<T> Decode for Option<T>
+where
+    T: Decode,
+{
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        match u8::decode(buf)? {
+            0 => Some(None),
+            1 => Some(Some(T::decode(buf)?)),
+            _ => None,
+        }
+    }
+}
@@ -1,5 +1,23 @@
 use super::Command;
 use crate::{AbstractState, Error};
+use std::collections::VecDeque;
+
+/// Observation of a command executed on both the model and the target.
+pub struct Feedback<'a, S>
+where
+    S: AbstractState,
+{
+    /// The executed command.
+    pub command: &'a dyn Command<S>,
+    /// Return value of the model.
+    pub model_retv: isize,
+    /// Return value of the target.
+    pub target_retv: isize,
+    /// State retrieved from the target after execution.
+    pub state: &'a S,
+    /// State of the model after execution, as predicted before comparing.
+    pub model_state: &'a S,
+}
 
 /// Generate commands for both the abstract model and the target kernel.
 pub trait Commander<S>
@@ -8,4 +26,44 @@ where
 {
     /// Get the next command to execute.
     fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error>;
+
+    /// (optional) Receive the observed result of the last command.
+    ///
+    /// Called by `Checker` once the target state has been retrieved, before it
+    /// is compared with the model.
+    fn feedback(&mut self, _feedback: &Feedback<S>) {}
+}
+
+/// Commander that yields a fixed sequence of commands, e.g. decoded from a trace.
+pub struct ReplayCommander<S>
+where
+    S: AbstractState,
+{
+    commands: VecDeque<Box<dyn Command<S>>>,
+}
+
+impl<S> ReplayCommander<S>
+where
+    S: AbstractState,
+{
+    /// Create a commander replaying the given commands in order.
+    pub fn new(commands: Vec<Box<dyn Command<S>>>) -> Self {
+        Self {
+            commands: commands.into(),
+        }
+    }
+
+    /// Number of commands left.
+    pub fn remaining(&self) -> usize {
+        self.commands.len()
+    }
+}
+
+impl<S> Commander<S> for ReplayCommander<S>
+where
+    S: AbstractState,
+{
+    fn command(&mut self, _state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        self.commands.pop_front().ok_or(Error::InvalidCommand)
+    }
 }
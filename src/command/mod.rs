@@ -1,8 +1,12 @@
+mod codec;
 mod commander;
+mod registry;
 
 use crate::AbstractState;
-pub use commander::Commander;
+pub use codec::{Decode, Encode};
+pub use commander::{Commander, Feedback, ReplayCommander};
 use core::fmt::Debug;
+pub use registry::{split_id, CommandId, CommandRegistry, DecodeFn, FromBytes, ID_LEN};
 
 /// A command that can be executed on a state.
 pub trait Command<T>: Debug
@@ -13,6 +17,58 @@ where
     fn execute(&self, state: &mut T) -> isize;
     /// Serialize the object to a byte array.
     fn to_bytes(&self) -> Vec<u8>;
+    /// Stable name of the command kind, defaults to the type name, i.e. the
+    /// leading identifier of the `Debug` output.
+    fn name(&self) -> String {
+        let repr = format!("{:?}", self);
+        let end = repr
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(repr.len());
+        repr[..end].to_string()
+    }
+    /// Command id, if the command has one.
+    fn id(&self) -> Option<u64> {
+        None
+    }
+    /// Category of the command.
+    fn category(&self) -> Category {
+        Category::Other
+    }
+    /// Whether the command leaves the state unchanged.
+    fn read_only(&self) -> bool {
+        false
+    }
+}
+
+/// Kind of a command: its id and name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandKind {
+    /// Command id.
+    pub id: u64,
+    /// Command name.
+    pub name: &'static str,
+}
+
+/// Category of a command, i.e. the kernel subsystem it exercises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Category {
+    /// File system.
+    Fs,
+    /// Memory management.
+    Mm,
+    /// Process and thread management.
+    Proc,
+    /// Inter-process communication.
+    Ipc,
+    /// Networking.
+    Net,
+    /// Time and timers.
+    Time,
+    /// Devices.
+    Dev,
+    /// Uncategorized.
+    #[default]
+    Other,
 }
 
 /// Default `to_bytes` implementation for model commands.
@@ -32,15 +88,19 @@ macro_rules! impl_to_bytes {
 
 /// Wrap a foreign-defined (typically `km_command`) command as a model
 /// command (command that can be executed on an abstract state) .
-/// Implement `Deref`, `From`, and `Debug` for it.
+/// Implement `Deref`, `From`, `Debug` and `CommandId` for it.
+///
+/// To decode it with a `CommandRegistry`, implement `FromBytes` as well.
 ///
 /// If `execute_fn` is provided, it will be used to implenment
-/// `Command` trait.
+/// `Command` trait. The category (a `Category` variant) and the read-only
+/// flag of the command may be given before it.
 ///
 /// Format:
 ///
 /// - `model_command!(module_name, command_name)`.
 /// - `model_command!(module_name, command_name, state_name, { execute_fn })`.
+/// - `model_command!(module_name, command_name, state_name, category = Fs, read_only, { execute_fn })`.
 #[macro_export]
 macro_rules! model_command {
     ($($mod:ident)::*,$cmd:ident$(<$lt:lifetime>)?) => {
@@ -51,6 +111,10 @@ macro_rules! model_command {
             pub const ID: usize = $($mod)::*::$cmd::ID;
         }
 
+        impl$(<$lt>)? $crate::CommandId for $cmd$(<$lt>)? {
+            const ID: u64 = $($mod)::*::$cmd::ID as u64;
+        }
+
         impl$(<$lt>)? core::ops::Deref for $cmd$(<$lt>)? {
             type Target = $($mod)::*::$cmd$(<$lt>)?;
 
@@ -71,7 +135,22 @@ macro_rules! model_command {
             }
         }
     };
-    ($($mod:ident)::*, $cmd:ident$(<$lt:lifetime>)?, $state:ident, $execute_fn:block) => {
+    (
+        $($mod:ident)::*, $cmd:ident$(<$lt:lifetime>)?, $state:ident,
+        $(category = $category:ident,)? read_only, $execute_fn:block
+    ) => {
+        model_command!(@impl $($mod)::*, $cmd$(<$lt>)?, $state, [$($category)?], [true], $execute_fn);
+    };
+    (
+        $($mod:ident)::*, $cmd:ident$(<$lt:lifetime>)?, $state:ident,
+        $(category = $category:ident,)? $execute_fn:block
+    ) => {
+        model_command!(@impl $($mod)::*, $cmd$(<$lt>)?, $state, [$($category)?], [], $execute_fn);
+    };
+    (
+        @impl $($mod:ident)::*, $cmd:ident$(<$lt:lifetime>)?, $state:ident,
+        [$($category:ident)?], [$($read_only:literal)?], $execute_fn:block
+    ) => {
         model_command!($($mod)::*, $cmd$(<$lt>)?);
 
         impl$(<$lt>)? $crate::Command<$state> for $cmd$(<$lt>)? {
@@ -99,6 +178,18 @@ macro_rules! model_command {
                 $execute_fn
             }
             $crate::impl_to_bytes!();
+            fn name(&self) -> String {
+                stringify!($cmd).to_string()
+            }
+            fn id(&self) -> Option<u64> {
+                Some(<Self as $crate::CommandId>::ID)
+            }
+            $(fn category(&self) -> $crate::Category {
+                $crate::Category::$category
+            })?
+            $(fn read_only(&self) -> bool {
+                $read_only
+            })?
         }
-    }
+    };
 }
@@ -0,0 +1,185 @@
+use super::Command;
+use crate::{AbstractState, Error};
+use std::collections::BTreeMap;
+
+/// Length of the command ID prefix in a serialized command.
+///
+/// A serialized command (`Command::to_bytes`) is the command ID as a
+/// little-endian `u64`, followed by the payload of the command:
+///
+/// ```text
+/// | id: u64 LE (8 bytes) | payload (FromBytes) |
+/// ```
+///
+/// Derived commands write this layout, commands implemented by hand must
+/// follow it to be decoded by a `CommandRegistry`.
+///
+/// `model_command!` wrappers keep the `km_command` encoding
+/// (`km_command::id_to_bytes`), which the target harness decodes. It writes
+/// the ID as a native-endian `usize`, so these commands are only decoded on
+/// 64-bit little-endian hosts, where both layouts coincide.
+pub const ID_LEN: usize = core::mem::size_of::<u64>();
+
+/// Command type with a unique ID, used as the prefix of its byte encoding.
+pub trait CommandId {
+    /// Command id.
+    const ID: u64;
+}
+
+/// Deserialize a command from its payload, i.e. `to_bytes` without the ID prefix.
+pub trait FromBytes: Sized {
+    /// Decode the payload, `None` if it is malformed.
+    fn from_bytes(buf: &[u8]) -> Option<Self>;
+}
+
+/// Split a serialized command into its ID and payload.
+///
+/// The ID is a little-endian `u64`.
+pub fn split_id(buf: &[u8]) -> Option<(u64, &[u8])> {
+    if buf.len() < ID_LEN {
+        return None;
+    }
+    let (id, payload) = buf.split_at(ID_LEN);
+    Some((u64::from_le_bytes(id.try_into().ok()?), payload))
+}
+
+/// Decode function of a command payload.
+pub type DecodeFn<S> = fn(&[u8]) -> Option<Box<dyn Command<S>>>;
+
+/// Decode a payload as command type `C`.
+fn decode_as<C, S>(buf: &[u8]) -> Option<Box<dyn Command<S>>>
+where
+    C: Command<S> + FromBytes + 'static,
+    S: AbstractState,
+{
+    C::from_bytes(buf).map(|cmd| Box::new(cmd) as Box<dyn Command<S>>)
+}
+
+/// Registry of model commands, decoding `to_bytes` output back into commands.
+pub struct CommandRegistry<S>
+where
+    S: AbstractState,
+{
+    /// Decode functions by command ID.
+    decoders: BTreeMap<u64, DecodeFn<S>>,
+}
+
+impl<S> CommandRegistry<S>
+where
+    S: AbstractState,
+{
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Register a command type under its `ID`.
+    ///
+    /// `DuplicateCommand` if the ID is already registered.
+    pub fn register<C>(&mut self) -> Result<&mut Self, Error>
+    where
+        C: Command<S> + CommandId + FromBytes + 'static,
+    {
+        self.register_fn(C::ID, decode_as::<C, S>)
+    }
+
+    /// Register a decode function under the given ID.
+    ///
+    /// `DuplicateCommand` if the ID is already registered, the earlier
+    /// function is kept.
+    pub fn register_fn(&mut self, id: u64, decode: DecodeFn<S>) -> Result<&mut Self, Error> {
+        if self.decoders.contains_key(&id) {
+            return Err(Error::DuplicateCommand);
+        }
+        self.decoders.insert(id, decode);
+        Ok(self)
+    }
+
+    /// Check if a command ID is registered.
+    pub fn contains(&self, id: u64) -> bool {
+        self.decoders.contains_key(&id)
+    }
+
+    /// Iterate over registered command IDs in ascending order.
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.decoders.keys().copied()
+    }
+
+    /// Decode a serialized command.
+    ///
+    /// `InvalidCommand` if the ID is unknown or the payload is malformed.
+    pub fn decode(&self, buf: &[u8]) -> Result<Box<dyn Command<S>>, Error> {
+        let (id, payload) = split_id(buf).ok_or(Error::InvalidCommand)?;
+        let decode = self.decoders.get(&id).ok_or(Error::InvalidCommand)?;
+        decode(payload).ok_or(Error::InvalidCommand)
+    }
+
+    /// Decode a sequence of serialized commands, e.g. a recorded trace.
+    pub fn decode_all<B>(&self, bufs: &[B]) -> Result<Vec<Box<dyn Command<S>>>, Error>
+    where
+        B: AsRef<[u8]>,
+    {
+        bufs.iter().map(|buf| self.decode(buf.as_ref())).collect()
+    }
+}
+
+impl<S> Default for CommandRegistry<S>
+where
+    S: AbstractState,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Value;
+
+    type S = Value<u8>;
+
+    #[derive(Debug)]
+    struct Nop;
+
+    impl Command<S> for Nop {
+        fn execute(&self, _state: &mut S) -> isize {
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Self::ID.to_le_bytes().to_vec()
+        }
+    }
+
+    impl CommandId for Nop {
+        const ID: u64 = 0x0102_0304_0506_0708;
+    }
+
+    impl FromBytes for Nop {
+        fn from_bytes(buf: &[u8]) -> Option<Self> {
+            buf.is_empty().then_some(Nop)
+        }
+    }
+
+    #[test]
+    fn id_is_u64_le() {
+        let bytes = Nop.to_bytes();
+        assert_eq!(bytes, [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(split_id(&bytes), Some((Nop::ID, &[][..])));
+        assert_eq!(split_id(&bytes[..ID_LEN - 1]), None);
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let mut registry = CommandRegistry::<S>::new();
+        assert!(registry.register::<Nop>().is_ok());
+        assert_eq!(
+            registry.register_fn(Nop::ID, |_| None).err(),
+            Some(Error::DuplicateCommand)
+        );
+        // The first decoder is kept.
+        assert!(registry.decode(&Nop.to_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{command::Feedback, AbstractState, Command, Commander, Error};
+use core::fmt::Debug;
+use std::collections::HashSet;
+
+/// Class of a command return value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RetvClass {
+    /// Negative return value, kept as is (typically an errno).
+    Error(isize),
+    /// Zero.
+    Zero,
+    /// Positive return value.
+    Positive,
+}
+
+impl From<isize> for RetvClass {
+    fn from(retv: isize) -> Self {
+        match retv {
+            0 => Self::Zero,
+            r if r < 0 => Self::Error(r),
+            _ => Self::Positive,
+        }
+    }
+}
+
+/// A coverage point: command kind, return value class and key of the
+/// resulting state (see `AbstractState::key`).
+///
+/// States that match give the same point, whatever their ignored or unknown
+/// parts.
+pub type CoveragePoint = (String, RetvClass, u64);
+
+/// Set of seen coverage points.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    points: HashSet<CoveragePoint>,
+}
+
+impl Coverage {
+    /// Create an empty coverage set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the coverage point of a command execution.
+    pub fn point<S>(command: &dyn Command<S>, retv: isize, state: &S) -> CoveragePoint
+    where
+        S: AbstractState,
+    {
+        (command.name(), retv.into(), state.key())
+    }
+
+    /// Build the coverage point of a checked command from the model.
+    ///
+    /// Points of all commanders and feedbacks come from the model, so that
+    /// they are comparable with the points `NoveltyCommander` predicts.
+    pub fn point_of<S>(feedback: &Feedback<S>) -> CoveragePoint
+    where
+        S: AbstractState,
+    {
+        Self::point(feedback.command, feedback.model_retv, feedback.model_state)
+    }
+
+    /// Check if a point has been seen.
+    pub fn contains(&self, point: &CoveragePoint) -> bool {
+        self.points.contains(point)
+    }
+
+    /// Record a point. Return `true` if it is new.
+    pub fn insert(&mut self, point: CoveragePoint) -> bool {
+        self.points.insert(point)
+    }
+
+    /// Number of seen points.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Check if no point has been seen.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Iterate over seen points.
+    pub fn iter(&self) -> impl Iterator<Item = &CoveragePoint> {
+        self.points.iter()
+    }
+}
+
+/// Commander that biases another commander towards novel behaviour.
+///
+/// Each round it draws several candidate commands from the inner commander,
+/// predicts their coverage point by executing them on a copy of the model state,
+/// and yields the first candidate that is predicted to reach an unseen point.
+/// Candidates drawn but not yielded are dropped, so the inner commander should
+/// generate commands rather than replay a sequence. Coverage is recorded from
+/// the model via `feedback` (see `Coverage::point_of`), so that recorded and
+/// predicted points are comparable even when model and target differ.
+pub struct NoveltyCommander<C> {
+    /// Inner command generator.
+    inner: C,
+    /// Number of candidates drawn each round.
+    candidates: usize,
+    /// Coverage reached by the model.
+    coverage: Coverage,
+    /// Number of rounds that reached a new point.
+    novel_rounds: usize,
+}
+
+impl<C> NoveltyCommander<C> {
+    /// Wrap a commander, drawing `candidates` commands each round.
+    pub fn new(inner: C, candidates: usize) -> Self {
+        Self {
+            inner,
+            candidates: candidates.max(1),
+            coverage: Coverage::new(),
+            novel_rounds: 0,
+        }
+    }
+
+    /// Get the coverage reached so far.
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    /// Number of rounds that reached a new coverage point.
+    pub fn novel_rounds(&self) -> usize {
+        self.novel_rounds
+    }
+}
+
+impl<C, S> Commander<S> for NoveltyCommander<C>
+where
+    C: Commander<S>,
+    S: AbstractState + Debug + Clone,
+{
+    fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        let mut first = None;
+        for _ in 0..self.candidates {
+            let command = self.inner.command(state)?;
+            let mut predicted = state.clone();
+            let retv = command.execute(&mut predicted);
+            if !self
+                .coverage
+                .contains(&Coverage::point(command.as_ref(), retv, &predicted))
+            {
+                return Ok(command);
+            }
+            first.get_or_insert(command);
+        }
+        first.ok_or(Error::InvalidCommand)
+    }
+
+    fn feedback(&mut self, feedback: &Feedback<S>) {
+        if self.coverage.insert(Coverage::point_of(feedback)) {
+            self.novel_rounds += 1;
+        }
+        self.inner.feedback(feedback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Ignored, Value};
+    use crate::ReplayCommander;
+    use std::collections::HashMap;
+
+    /// Counters by name, with a value the checker ignores.
+    type State = (HashMap<String, Value<u32>>, Ignored<u32>);
+
+    /// Command bumping a counter.
+    #[derive(Debug)]
+    struct Bump(&'static str);
+
+    impl Command<State> for Bump {
+        fn execute(&self, state: &mut State) -> isize {
+            state.0.entry(self.0.to_string()).or_default().0 += 1;
+            state.1 .0 += 1;
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    fn state(counters: &[(&str, u32)], ignored: u32) -> State {
+        let counters = counters
+            .iter()
+            .map(|&(name, n)| (name.to_string(), Value(n)))
+            .collect();
+        (counters, Ignored(ignored))
+    }
+
+    #[test]
+    fn matching_states_give_the_same_point() {
+        let names: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let counters: Vec<(&str, u32)> = names.iter().map(|n| (n.as_str(), 1)).collect();
+        let a = state(&counters, 1);
+        let mut reversed = counters.clone();
+        reversed.reverse();
+        let b = state(&reversed, 2);
+        assert!(a.matches(&b));
+        assert_ne!(format!("{:?}", a.1), format!("{:?}", b.1));
+        assert_eq!(
+            Coverage::point(&Bump("0"), 0, &a),
+            Coverage::point(&Bump("0"), 0, &b)
+        );
+        let c = state(&[("0", 2)], 1);
+        assert_ne!(
+            Coverage::point(&Bump("0"), 0, &a),
+            Coverage::point(&Bump("0"), 0, &c)
+        );
+    }
+
+    #[test]
+    fn novelty_drops_rejected_candidates() {
+        let commands: Vec<Box<dyn Command<State>>> = vec![
+            Box::new(Bump("a")),
+            Box::new(Bump("b")),
+            Box::new(Bump("c")),
+        ];
+        let mut commander = NoveltyCommander::new(ReplayCommander::new(commands), 2);
+        let initial = state(&[], 0);
+        let mut seen = initial.clone();
+        let retv = Bump("a").execute(&mut seen);
+        commander.feedback(&Feedback {
+            command: &Bump("a"),
+            model_retv: retv,
+            target_retv: retv,
+            state: &seen,
+            model_state: &seen,
+        });
+        assert_eq!(commander.coverage().len(), 1);
+        // `a` reaches a seen point, `b` is yielded and `a` is gone.
+        let command = commander.command(&initial).unwrap();
+        assert_eq!(format!("{:?}", command), r#"Bump("b")"#);
+        assert_eq!(commander.inner.remaining(), 1);
+        let command = commander.command(&initial).unwrap();
+        assert_eq!(format!("{:?}", command), r#"Bump("c")"#);
+        assert_eq!(commander.inner.remaining(), 0);
+    }
+}
@@ -6,4 +6,10 @@ pub enum Error {
     StateMismatch,
     /// Return value check failed
     ReturnValueMismatch,
+    /// Command is not available
+    InvalidCommand,
+    /// Malformed input file
+    Parse,
+    /// Command ID registered twice
+    DuplicateCommand,
 }
@@ -0,0 +1,318 @@
+use super::{AlphabetCommander, CommandAlphabet};
+use crate::{
+    port::{ResetTarget, RestoreTarget, TestPort},
+    AbstractState, CheckLevel, Checker, Error, Printer,
+};
+use core::fmt::Debug;
+
+/// A command sequence that failed the check.
+#[derive(Debug, Clone)]
+pub struct ExplorationFailure {
+    /// Alphabet index of each command in the sequence.
+    pub choices: Vec<usize>,
+    /// Debug strings of the commands in the sequence.
+    pub commands: Vec<String>,
+    /// Check error of the last command.
+    pub error: Error,
+}
+
+/// Result of a bounded exploration.
+#[derive(Debug, Clone, Default)]
+pub struct Exploration {
+    /// Number of command sequences that were executed.
+    pub sequences: usize,
+    /// Sequences that failed the check. Their extensions are not explored.
+    pub failures: Vec<ExplorationFailure>,
+}
+
+/// Bounded exhaustive explorer.
+///
+/// Enumerates every command sequence up to a given depth from a finite command
+/// alphabet and checks each of them with a `Checker`. Between branches the target
+/// is rolled back, either by snapshot/restore or by a full reset and replay.
+pub struct BoundedExplorer<A, T, P, S>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S>,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+{
+    /// Checker that runs the sequences.
+    checker: Checker<AlphabetCommander<A>, T, P, S>,
+    /// Model state before initialization.
+    initial: S,
+    /// Maximum sequence length.
+    depth: usize,
+    /// Check level of return values.
+    retv_level: CheckLevel,
+    /// Check level of states.
+    state_level: CheckLevel,
+}
+
+impl<A, T, P, S> BoundedExplorer<A, T, P, S>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S>,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+{
+    /// Construct an explorer of sequences up to `depth` commands.
+    ///
+    /// Check levels default to `Strict`.
+    pub fn new(alphabet: A, port: T, printer: P, state: S, depth: usize) -> Self {
+        Self {
+            checker: Checker::new(
+                AlphabetCommander::new(alphabet),
+                port,
+                printer,
+                state.clone(),
+            ),
+            initial: state,
+            depth,
+            retv_level: CheckLevel::Strict,
+            state_level: CheckLevel::Strict,
+        }
+    }
+
+    /// Set check levels of return values and states.
+    ///
+    /// Only `Strict` mismatches are reported as failures.
+    pub fn check_levels(mut self, retv_level: CheckLevel, state_level: CheckLevel) -> Self {
+        self.retv_level = retv_level;
+        self.state_level = state_level;
+        self
+    }
+
+    /// Explore all sequences, rolling back the target with snapshots.
+    pub fn explore_with_snapshots(&mut self) -> Result<Exploration, Error>
+    where
+        T: RestoreTarget,
+    {
+        let mut result = Exploration::default();
+        self.checker.reset(self.initial.clone());
+        self.checker.run_round(self.retv_level, self.state_level)?;
+        self.explore_snapshot(&mut Vec::new(), &mut result)?;
+        Ok(result)
+    }
+
+    /// Explore all sequences, resetting the target and replaying the prefix
+    /// before each branch.
+    pub fn explore_with_resets(&mut self) -> Result<Exploration, Error>
+    where
+        T: ResetTarget,
+    {
+        let mut result = Exploration::default();
+        self.replay(&[])?;
+        self.explore_reset(&mut Vec::new(), &mut result)?;
+        Ok(result)
+    }
+
+    /// Get a reference to the inner checker.
+    pub fn checker(&self) -> &Checker<AlphabetCommander<A>, T, P, S> {
+        &self.checker
+    }
+
+    fn explore_snapshot(
+        &mut self,
+        path: &mut Vec<usize>,
+        result: &mut Exploration,
+    ) -> Result<(), Error>
+    where
+        T: RestoreTarget,
+    {
+        if path.len() >= self.depth {
+            return Ok(());
+        }
+        let snapshot = self.checker.port_mut().snapshot()?;
+        let state = self.checker.state().clone();
+        let round = self.checker.round();
+        let width = self.width();
+        for choice in 0..width {
+            if choice > 0 {
+                self.checker.port_mut().restore(&snapshot)?;
+                self.checker.restore(state.clone(), round);
+            }
+            path.push(choice);
+            if self.run(path, result)? {
+                self.explore_snapshot(path, result)?;
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn explore_reset(
+        &mut self,
+        path: &mut Vec<usize>,
+        result: &mut Exploration,
+    ) -> Result<(), Error>
+    where
+        T: ResetTarget,
+    {
+        if path.len() >= self.depth {
+            return Ok(());
+        }
+        let width = self.width();
+        for choice in 0..width {
+            if choice > 0 {
+                self.replay(path)?;
+            }
+            path.push(choice);
+            if self.run(path, result)? {
+                self.explore_reset(path, result)?;
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Reset target and checker, then execute the given prefix.
+    fn replay(&mut self, path: &[usize]) -> Result<(), Error>
+    where
+        T: ResetTarget,
+    {
+        self.checker.port_mut().reset()?;
+        self.checker.reset(self.initial.clone());
+        self.checker.commander_mut().truncate_history(0);
+        self.checker.run_round(self.retv_level, self.state_level)?;
+        for &choice in path {
+            self.checker.commander_mut().choose(choice);
+            self.checker.run_round(self.retv_level, self.state_level)?;
+        }
+        Ok(())
+    }
+
+    /// Number of commands available in current state.
+    fn width(&self) -> usize {
+        let alphabet = self.checker.commander().alphabet();
+        alphabet.commands(self.checker.state()).len()
+    }
+
+    /// Execute the last command of `path`. Return whether the check passed.
+    fn run(&mut self, path: &[usize], result: &mut Exploration) -> Result<bool, Error> {
+        let commander = self.checker.commander_mut();
+        commander.truncate_history(path.len() - 1);
+        commander.choose(path[path.len() - 1]);
+        result.sequences += 1;
+        match self.checker.run_round(self.retv_level, self.state_level) {
+            Ok(()) => Ok(true),
+            Err(error @ (Error::StateMismatch | Error::ReturnValueMismatch)) => {
+                result.failures.push(ExplorationFailure {
+                    choices: path.to_vec(),
+                    commands: self.checker.commander_mut().history().to_vec(),
+                    error,
+                });
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{port::MockTestPort, Command};
+
+    /// Stack depth. `buggy` is the target's own and makes the third element
+    /// count twice.
+    #[derive(Debug, Clone, Default)]
+    struct Stack {
+        len: u32,
+        buggy: bool,
+    }
+
+    impl AbstractState for Stack {
+        fn matches(&self, other: &Self) -> bool {
+            self.len == other.len
+        }
+        fn update(&mut self, other: &Self) {
+            self.len = other.len;
+        }
+    }
+
+    #[derive(Debug)]
+    struct Push;
+
+    impl Command<Stack> for Push {
+        fn execute(&self, state: &mut Stack) -> isize {
+            state.len += 1;
+            if state.buggy && state.len == 3 {
+                state.len += 1;
+            }
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    #[derive(Debug)]
+    struct Pop;
+
+    impl Command<Stack> for Pop {
+        fn execute(&self, state: &mut Stack) -> isize {
+            state.len = state.len.saturating_sub(1);
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    type Alphabet = fn(&Stack) -> Vec<Box<dyn Command<Stack>>>;
+
+    fn alphabet(_: &Stack) -> Vec<Box<dyn Command<Stack>>> {
+        vec![Box::new(Push), Box::new(Pop)]
+    }
+
+    struct Quiet;
+
+    impl Printer for Quiet {
+        fn print(&mut self, _s: &str) {}
+    }
+
+    /// Explore a buggy target with snapshots, then with resets.
+    fn explore(depth: usize) -> [Exploration; 2] {
+        let explorer = || {
+            let target = Stack {
+                len: 0,
+                buggy: true,
+            };
+            let port = MockTestPort::new(target);
+            BoundedExplorer::new(alphabet as Alphabet, port, Quiet, Stack::default(), depth)
+        };
+        [
+            explorer().explore_with_snapshots().unwrap(),
+            explorer().explore_with_resets().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn depth_bounds_the_sequences() {
+        for result in explore(2) {
+            assert_eq!(result.sequences, 2 + 4);
+            assert!(result.failures.is_empty());
+        }
+    }
+
+    #[test]
+    fn planted_mismatch_is_found() {
+        for result in explore(4) {
+            // Extensions of the failing `[0, 0, 0]` are not explored.
+            assert_eq!(result.sequences, 2 + 4 + 8 + 16 - 2);
+            let choices: Vec<&[usize]> = result
+                .failures
+                .iter()
+                .map(|f| f.choices.as_slice())
+                .collect();
+            assert_eq!(choices, [&[0, 0, 0][..], &[1, 0, 0, 0]]);
+            assert_eq!(result.failures[0].commands, ["Push", "Push", "Push"]);
+            assert_eq!(result.failures[1].commands, ["Pop", "Push", "Push", "Push"]);
+            assert!(result
+                .failures
+                .iter()
+                .all(|f| f.error == Error::StateMismatch));
+        }
+    }
+}
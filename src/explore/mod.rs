@@ -0,0 +1,153 @@
+mod bounded;
+mod model;
+
+use crate::{AbstractState, Command, Commander, Error};
+pub use bounded::{BoundedExplorer, Exploration, ExplorationFailure};
+pub use model::{ModelExplorer, ModelPanic, StateGraph, Transition};
+use std::collections::VecDeque;
+
+/// A finite set of commands that can be chosen at each step of exploration.
+pub trait CommandAlphabet<S>
+where
+    S: AbstractState,
+{
+    /// Enumerate all commands that can be executed on the given state.
+    ///
+    /// The order must be deterministic, a command is identified by its index.
+    fn commands(&self, state: &S) -> Vec<Box<dyn Command<S>>>;
+}
+
+impl<S, F> CommandAlphabet<S> for F
+where
+    S: AbstractState,
+    F: Fn(&S) -> Vec<Box<dyn Command<S>>>,
+{
+    fn commands(&self, state: &S) -> Vec<Box<dyn Command<S>>> {
+        self(state)
+    }
+}
+
+/// Commander that yields chosen commands from an alphabet.
+pub struct AlphabetCommander<A> {
+    /// Command alphabet.
+    alphabet: A,
+    /// Indices of the next commands.
+    choices: VecDeque<usize>,
+    /// Debug strings of yielded commands.
+    history: Vec<String>,
+}
+
+impl<A> AlphabetCommander<A> {
+    /// Create a commander over the given alphabet.
+    pub fn new(alphabet: A) -> Self {
+        Self {
+            alphabet,
+            choices: VecDeque::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Create a commander that replays a sequence of choices, e.g. one found
+    /// by `ModelExplorer`.
+    pub fn replay(alphabet: A, choices: &[usize]) -> Self {
+        let mut commander = Self::new(alphabet);
+        commander.choices.extend(choices);
+        commander
+    }
+
+    /// Choose the command index for the next round, discarding pending choices.
+    pub fn choose(&mut self, choice: usize) {
+        self.choices.clear();
+        self.choices.push_back(choice);
+    }
+
+    /// Get the command alphabet.
+    pub fn alphabet(&self) -> &A {
+        &self.alphabet
+    }
+
+    /// Debug strings of commands yielded so far.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Forget commands yielded after the first `len` ones.
+    pub fn truncate_history(&mut self, len: usize) {
+        self.history.truncate(len);
+    }
+}
+
+impl<S, A> Commander<S> for AlphabetCommander<A>
+where
+    S: AbstractState,
+    A: CommandAlphabet<S>,
+{
+    fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        let mut commands = self.alphabet.commands(state);
+        let choice = self.choices.pop_front().ok_or(Error::InvalidCommand)?;
+        if choice >= commands.len() {
+            return Err(Error::InvalidCommand);
+        }
+        let command = commands.swap_remove(choice);
+        self.history.push(format!("{:?}", command));
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Value;
+
+    #[derive(Debug)]
+    struct Add(u32);
+
+    impl Command<Value<u32>> for Add {
+        fn execute(&self, state: &mut Value<u32>) -> isize {
+            state.0 += self.0;
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    /// Adds of 1 up to the current value, plus one.
+    fn alphabet(state: &Value<u32>) -> Vec<Box<dyn Command<Value<u32>>>> {
+        (1..=state.0 + 1)
+            .map(|n| Box::new(Add(n)) as Box<dyn Command<_>>)
+            .collect()
+    }
+
+    #[test]
+    fn replayed_choices_depend_on_the_state() {
+        let mut commander = AlphabetCommander::replay(alphabet, &[0, 1, 3]);
+        let mut state = Value(0);
+        for _ in 0..3 {
+            let command = commander.command(&state).unwrap();
+            command.execute(&mut state);
+        }
+        assert_eq!(state.0, 1 + 2 + 4);
+        assert_eq!(commander.history(), ["Add(1)", "Add(2)", "Add(4)"]);
+        // No choice left.
+        assert!(matches!(
+            commander.command(&state),
+            Err(Error::InvalidCommand)
+        ));
+        commander.truncate_history(1);
+        assert_eq!(commander.history(), ["Add(1)"]);
+    }
+
+    #[test]
+    fn choices_out_of_the_alphabet_are_invalid() {
+        let mut commander = AlphabetCommander::replay(alphabet, &[0, 0]);
+        commander.choose(1);
+        assert!(matches!(
+            commander.command(&Value(0)),
+            Err(Error::InvalidCommand)
+        ));
+        // `choose` discarded the replayed choices.
+        assert!(commander.command(&Value(0)).is_err());
+        assert!(commander.history().is_empty());
+    }
+}
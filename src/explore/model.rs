@@ -0,0 +1,322 @@
+use super::CommandAlphabet;
+use crate::AbstractState;
+use core::fmt::Debug;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+/// An edge of the state graph.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// Source state index.
+    pub from: usize,
+    /// Alphabet index of the command.
+    pub choice: usize,
+    /// Kind of the command.
+    pub kind: String,
+    /// Return value of the command.
+    pub retv: isize,
+    /// Destination state index.
+    pub to: usize,
+}
+
+/// A command that panicked when executed on the model.
+#[derive(Debug, Clone)]
+pub struct ModelPanic {
+    /// Choices leading to the panic, the last one is the panicking command.
+    pub choices: Vec<usize>,
+    /// Debug string of the panicking command.
+    pub command: String,
+    /// Panic message.
+    pub message: String,
+}
+
+/// Reachable state graph of a model.
+#[derive(Debug, Clone)]
+pub struct StateGraph<S> {
+    /// Distinct reachable states. Index 0 is the initial state.
+    pub states: Vec<S>,
+    /// Shortest choice sequence reaching each state.
+    pub paths: Vec<Vec<usize>>,
+    /// All executed transitions.
+    pub transitions: Vec<Transition>,
+    /// Commands that panicked.
+    pub panics: Vec<ModelPanic>,
+    /// Whether the outgoing transitions of each state were explored.
+    expanded: Vec<bool>,
+}
+
+impl<S> StateGraph<S> {
+    /// Choice sequences reaching each distinct state, to be replayed against
+    /// the target.
+    pub fn sequences(&self) -> &[Vec<usize>] {
+        &self.paths
+    }
+
+    /// Explored states that have no transition to another state.
+    pub fn dead_states(&self) -> Vec<usize> {
+        (0..self.states.len())
+            .filter(|&i| self.expanded[i])
+            .filter(|&i| self.transitions.iter().all(|t| t.from != i || t.to == i))
+            .collect()
+    }
+
+    /// Command kinds that never succeeded (returned a negative value in every
+    /// reachable state), together with the number of executions.
+    pub fn unreachable_commands(&self) -> Vec<(String, usize)> {
+        let mut kinds: BTreeMap<&str, (usize, bool)> = BTreeMap::new();
+        for t in &self.transitions {
+            let entry = kinds.entry(&t.kind).or_default();
+            entry.0 += 1;
+            entry.1 |= t.retv >= 0;
+        }
+        kinds
+            .into_iter()
+            .filter(|(_, (_, succeeded))| !succeeded)
+            .map(|(kind, (count, _))| (kind.to_string(), count))
+            .collect()
+    }
+}
+
+/// Model-only state-space explorer.
+///
+/// Executes commands from an alphabet on the model alone, without a test
+/// target, and builds the graph of distinct reachable states in breadth-first
+/// order up to a depth bound. Two states are the same if they match each
+/// other, so fields the model doesn't check (e.g. `Ignored`) don't create
+/// new states. A new state is only compared with the states of the same key
+/// (see `AbstractState::key`), states without keys with all of them.
+pub struct ModelExplorer<A, S>
+where
+    A: CommandAlphabet<S>,
+    S: AbstractState + Debug + Clone,
+{
+    /// Command alphabet.
+    alphabet: A,
+    /// Initial model state.
+    initial: S,
+    /// Maximum sequence length.
+    depth: usize,
+}
+
+impl<A, S> ModelExplorer<A, S>
+where
+    A: CommandAlphabet<S>,
+    S: AbstractState + Debug + Clone,
+{
+    /// Construct an explorer of sequences up to `depth` commands.
+    pub fn new(alphabet: A, initial: S, depth: usize) -> Self {
+        Self {
+            alphabet,
+            initial,
+            depth,
+        }
+    }
+
+    /// Explore the reachable state graph.
+    pub fn explore(&self) -> StateGraph<S> {
+        let mut graph = StateGraph {
+            states: vec![self.initial.clone()],
+            paths: vec![Vec::new()],
+            transitions: Vec::new(),
+            panics: Vec::new(),
+            expanded: vec![false],
+        };
+        // Indices of the states of each key.
+        let mut keys: HashMap<u64, Vec<usize>> = HashMap::from([(self.initial.key(), vec![0])]);
+        let mut queue = VecDeque::from([0]);
+        while let Some(from) = queue.pop_front() {
+            if graph.paths[from].len() >= self.depth {
+                continue;
+            }
+            graph.expanded[from] = true;
+            let commands = self.alphabet.commands(&graph.states[from]);
+            for (choice, command) in commands.iter().enumerate() {
+                let mut state = graph.states[from].clone();
+                let retv = match catch_unwind(AssertUnwindSafe(|| command.execute(&mut state))) {
+                    Ok(retv) => retv,
+                    Err(payload) => {
+                        let message = payload
+                            .downcast_ref::<&str>()
+                            .map(|s| s.to_string())
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+                        let mut choices = graph.paths[from].clone();
+                        choices.push(choice);
+                        graph.panics.push(ModelPanic {
+                            choices,
+                            command: format!("{:?}", command),
+                            message,
+                        });
+                        continue;
+                    }
+                };
+                let same = keys.entry(state.key()).or_default();
+                let known = same.iter().copied().find(|&i| {
+                    let s = &graph.states[i];
+                    s.matches(&state) && state.matches(s)
+                });
+                let to = known.unwrap_or_else(|| {
+                    let mut path = graph.paths[from].clone();
+                    path.push(choice);
+                    same.push(graph.states.len());
+                    graph.states.push(state);
+                    graph.paths.push(path);
+                    graph.expanded.push(false);
+                    queue.push_back(graph.states.len() - 1);
+                    graph.states.len() - 1
+                });
+                graph.transitions.push(Transition {
+                    from,
+                    choice,
+                    kind: command.name(),
+                    retv,
+                    to,
+                });
+            }
+        }
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{Ignored, Value},
+        Command,
+    };
+    use std::collections::HashMap;
+
+    /// Toggled flag, with a counter of executions and a map the model
+    /// doesn't check.
+    type Toggle = (Value<bool>, Ignored<u32>, Ignored<HashMap<u32, u32>>);
+
+    #[derive(Debug)]
+    struct Flip;
+
+    impl Command<Toggle> for Flip {
+        fn execute(&self, state: &mut Toggle) -> isize {
+            state.0 .0 = !state.0 .0;
+            state.1 .0 += 1;
+            state.2.insert(state.1 .0, 0);
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn unchecked_fields_dont_create_states() {
+        let alphabet = |_: &Toggle| vec![Box::new(Flip) as Box<dyn Command<Toggle>>];
+        let graph = ModelExplorer::new(alphabet, Toggle::default(), 6).explore();
+        assert_eq!(graph.states.len(), 2);
+        assert_eq!(graph.sequences(), [vec![], vec![0]]);
+        assert_eq!(graph.transitions.len(), 2);
+        assert!(graph.dead_states().is_empty());
+    }
+
+    /// Counter modulo 3, without a key.
+    #[derive(Debug, Clone, Default)]
+    struct Mod3(u32);
+
+    impl AbstractState for Mod3 {
+        fn matches(&self, other: &Self) -> bool {
+            self.0 % 3 == other.0 % 3
+        }
+        fn update(&mut self, other: &Self) {
+            self.0 = other.0;
+        }
+    }
+
+    #[derive(Debug)]
+    struct Inc;
+
+    impl<S> Command<S> for Inc
+    where
+        S: AbstractState + core::ops::DerefMut<Target = u32>,
+    {
+        fn execute(&self, state: &mut S) -> isize {
+            **state += 1;
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    impl core::ops::Deref for Mod3 {
+        type Target = u32;
+        fn deref(&self) -> &u32 {
+            &self.0
+        }
+    }
+
+    impl core::ops::DerefMut for Mod3 {
+        fn deref_mut(&mut self) -> &mut u32 {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn states_without_keys_are_compared() {
+        let alphabet = |_: &Mod3| vec![Box::new(Inc) as Box<dyn Command<Mod3>>];
+        let graph = ModelExplorer::new(alphabet, Mod3::default(), 10).explore();
+        assert_eq!(graph.states.len(), 3);
+        assert_eq!(graph.sequences(), [vec![], vec![0], vec![0, 0]]);
+        assert_eq!(graph.transitions.last().unwrap().to, 0);
+    }
+
+    /// Command failing on even values.
+    #[derive(Debug)]
+    struct Odd;
+
+    impl Command<Value<u32>> for Odd {
+        fn execute(&self, state: &mut Value<u32>) -> isize {
+            (state.0 % 2) as isize - 1
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    /// Command panicking at 2.
+    #[derive(Debug)]
+    struct Boom;
+
+    impl Command<Value<u32>> for Boom {
+        fn execute(&self, state: &mut Value<u32>) -> isize {
+            assert_ne!(state.0, 2, "boom");
+            0
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    fn counter(_: &Value<u32>) -> Vec<Box<dyn Command<Value<u32>>>> {
+        vec![Box::new(Inc), Box::new(Odd), Box::new(Boom)]
+    }
+
+    #[test]
+    fn depth_bounds_the_graph() {
+        let graph = ModelExplorer::new(counter, Value(0), 4).explore();
+        assert_eq!(graph.states.len(), 5);
+        for (i, path) in graph.sequences().iter().enumerate() {
+            assert_eq!(path, &vec![0; i]);
+            assert_eq!(graph.states[i].0, i as u32);
+        }
+        // The last state is not expanded, the others loop with `Odd`.
+        assert!(graph.transitions.iter().all(|t| t.from < 4));
+        assert_eq!(graph.dead_states(), Vec::<usize>::new());
+        assert_eq!(graph.panics.len(), 1);
+        assert_eq!(graph.panics[0].choices, vec![0, 0, 2]);
+        assert_eq!(graph.panics[0].command, "Boom");
+        assert!(graph.panics[0].message.contains("boom"));
+        assert!(graph.unreachable_commands().is_empty());
+        let graph = ModelExplorer::new(counter, Value(0), 1).explore();
+        assert_eq!(graph.unreachable_commands(), vec![("Odd".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,192 @@
+use super::{CheckOutcome, CommandSeqInput, SharedOutcome};
+use crate::{
+    command::Feedback,
+    explore::{AlphabetCommander, CommandAlphabet},
+    port::{ResetTarget, TestPort},
+    AbstractState, CheckLevel, Checker, Command, Commander, Coverage, CoveragePoint, Error,
+    Printer,
+};
+use core::{fmt::Debug, marker::PhantomData};
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::UsesInput,
+    state::{HasExecutions, State, UsesState},
+};
+use libafl_bolts::tuples::RefIndexable;
+
+/// Alphabet commander that records coverage points of executed commands, see
+/// `Coverage::point_of`.
+pub struct RecordingCommander<A> {
+    inner: AlphabetCommander<A>,
+    points: Vec<CoveragePoint>,
+}
+
+impl<A, S> Commander<S> for RecordingCommander<A>
+where
+    A: CommandAlphabet<S>,
+    S: AbstractState + Debug,
+{
+    fn command(&mut self, state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        self.inner.command(state)
+    }
+
+    fn feedback(&mut self, feedback: &Feedback<S>) {
+        self.points.push(Coverage::point_of(feedback));
+    }
+}
+
+/// LibAFL executor that checks a command sequence with a `Checker`.
+///
+/// Before each run the target is reset and the initial state retrieved again.
+/// Check results are published to a `SharedOutcome` read by `MismatchFeedback`
+/// and `NoveltyFeedback`.
+pub struct CheckerExecutor<A, T, P, S, OT, ST>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S> + ResetTarget,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+{
+    /// Checker that runs the sequences.
+    checker: Checker<RecordingCommander<A>, T, P, S>,
+    /// Model state before initialization.
+    initial: S,
+    /// Check level of return values.
+    retv_level: CheckLevel,
+    /// Check level of states.
+    state_level: CheckLevel,
+    /// Outcome of the last run.
+    outcome: SharedOutcome,
+    /// Observers of the executor.
+    observers: OT,
+    phantom: PhantomData<ST>,
+}
+
+impl<A, T, P, S, OT, ST> CheckerExecutor<A, T, P, S, OT, ST>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S> + ResetTarget,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+{
+    /// Construct an executor. Check levels default to `Strict`.
+    pub fn new(alphabet: A, port: T, printer: P, state: S, observers: OT) -> Self {
+        let commander = RecordingCommander {
+            inner: AlphabetCommander::new(alphabet),
+            points: Vec::new(),
+        };
+        Self {
+            checker: Checker::new(commander, port, printer, state.clone()),
+            initial: state,
+            retv_level: CheckLevel::Strict,
+            state_level: CheckLevel::Strict,
+            outcome: SharedOutcome::default(),
+            observers,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set check levels of return values and states.
+    pub fn check_levels(mut self, retv_level: CheckLevel, state_level: CheckLevel) -> Self {
+        self.retv_level = retv_level;
+        self.state_level = state_level;
+        self
+    }
+
+    /// Get the shared outcome, to construct feedbacks.
+    pub fn outcome(&self) -> SharedOutcome {
+        self.outcome.clone()
+    }
+
+    /// Reset the target and check a choice sequence.
+    fn run(&mut self, choices: &[usize]) -> Result<(), Error> {
+        self.checker.port_mut().reset()?;
+        self.checker.reset(self.initial.clone());
+        self.checker.run_round(self.retv_level, self.state_level)?;
+        for &choice in choices {
+            let width = self
+                .checker
+                .commander()
+                .inner
+                .alphabet()
+                .commands(self.checker.state())
+                .len();
+            if width == 0 {
+                break;
+            }
+            self.checker.commander_mut().inner.choose(choice % width);
+            self.checker.run_round(self.retv_level, self.state_level)?;
+        }
+        Ok(())
+    }
+}
+
+impl<A, T, P, S, OT, ST> UsesState for CheckerExecutor<A, T, P, S, OT, ST>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S> + ResetTarget,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+    ST: State + UsesInput<Input = CommandSeqInput>,
+{
+    type State = ST;
+}
+
+impl<A, T, P, S, OT, ST, EM, Z> Executor<EM, Z> for CheckerExecutor<A, T, P, S, OT, ST>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S> + ResetTarget,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+    ST: State + UsesInput<Input = CommandSeqInput> + HasExecutions,
+    EM: UsesState<State = ST>,
+    Z: UsesState<State = ST>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut ST,
+        _mgr: &mut EM,
+        input: &CommandSeqInput,
+    ) -> Result<ExitKind, libafl::Error> {
+        *state.executions_mut() += 1;
+        let mut outcome = CheckOutcome::default();
+        let result = self.run(&input.choices);
+        // Taken on every run, so that points of a failed run don't leak into
+        // the next one.
+        let points = core::mem::take(&mut self.checker.commander_mut().points);
+        match result {
+            Ok(()) => {}
+            Err(error @ (Error::StateMismatch | Error::ReturnValueMismatch)) => {
+                outcome.error = Some(error);
+            }
+            Err(error) => {
+                return Err(libafl::Error::illegal_state(format!(
+                    "Checker failed: {:?}",
+                    error
+                )));
+            }
+        }
+        outcome.points = points;
+        *self.outcome.borrow_mut() = outcome;
+        Ok(ExitKind::Ok)
+    }
+}
+
+impl<A, T, P, S, OT, ST> HasObservers for CheckerExecutor<A, T, P, S, OT, ST>
+where
+    A: CommandAlphabet<S>,
+    T: TestPort<S> + ResetTarget,
+    P: Printer,
+    S: AbstractState + Debug + Clone,
+{
+    type Observers = OT;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
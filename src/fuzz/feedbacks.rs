@@ -0,0 +1,125 @@
+use super::SharedOutcome;
+use crate::{Coverage, CoveragePoint, Error};
+use libafl::{
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+};
+use libafl_bolts::Named;
+use std::borrow::Cow;
+
+/// Objective that fires when the checker found a state or return value mismatch.
+pub struct MismatchFeedback {
+    name: Cow<'static, str>,
+    outcome: SharedOutcome,
+}
+
+impl MismatchFeedback {
+    /// Create a feedback reading the outcome of a `CheckerExecutor`.
+    pub fn new(outcome: SharedOutcome) -> Self {
+        Self {
+            name: Cow::Borrowed("MismatchFeedback"),
+            outcome,
+        }
+    }
+}
+
+impl Named for MismatchFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for MismatchFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for MismatchFeedback {
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error> {
+        Ok(matches!(
+            self.outcome.borrow().error,
+            Some(Error::StateMismatch | Error::ReturnValueMismatch)
+        ))
+    }
+}
+
+/// Feedback that considers an input interesting if it reached a new coverage
+/// point (command kind, return value class, model state key), see
+/// `Coverage::point_of`.
+///
+/// New points are only added to the coverage once the input is added to the
+/// corpus (`append_metadata`), not when it is found interesting.
+pub struct NoveltyFeedback {
+    name: Cow<'static, str>,
+    outcome: SharedOutcome,
+    coverage: Coverage,
+    /// New points of the last interesting input.
+    pending: Vec<CoveragePoint>,
+}
+
+impl NoveltyFeedback {
+    /// Create a feedback reading the outcome of a `CheckerExecutor`.
+    pub fn new(outcome: SharedOutcome) -> Self {
+        Self {
+            name: Cow::Borrowed("NoveltyFeedback"),
+            outcome,
+            coverage: Coverage::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Get the coverage seen so far.
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+}
+
+impl Named for NoveltyFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for NoveltyFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for NoveltyFeedback {
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, libafl::Error> {
+        self.pending.clear();
+        for point in &self.outcome.borrow().points {
+            if !self.coverage.contains(point) && !self.pending.contains(point) {
+                self.pending.push(point.clone());
+            }
+        }
+        Ok(!self.pending.is_empty())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        _testcase: &mut Testcase<I>,
+    ) -> Result<(), libafl::Error> {
+        for point in self.pending.drain(..) {
+            self.coverage.insert(point);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), libafl::Error> {
+        self.pending.clear();
+        Ok(())
+    }
+}
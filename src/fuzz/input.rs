@@ -0,0 +1,40 @@
+use libafl::{corpus::CorpusId, inputs::Input};
+use libafl_bolts::HasLen;
+use serde::{Deserialize, Serialize};
+
+/// Fuzzer input holding a command sequence.
+///
+/// Each element chooses a command from a `CommandAlphabet`. The choice is taken
+/// modulo the number of commands available in the current state, so every
+/// sequence is executable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CommandSeqInput {
+    /// Alphabet index of each command.
+    pub choices: Vec<usize>,
+}
+
+impl CommandSeqInput {
+    /// Create an input from a choice sequence.
+    pub fn new(choices: Vec<usize>) -> Self {
+        Self { choices }
+    }
+}
+
+impl Input for CommandSeqInput {
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let hash = crate::state::fingerprint(&self.choices);
+        format!("cmdseq-{:016x}", hash)
+    }
+}
+
+impl HasLen for CommandSeqInput {
+    fn len(&self) -> usize {
+        self.choices.len()
+    }
+}
+
+impl From<Vec<usize>> for CommandSeqInput {
+    fn from(choices: Vec<usize>) -> Self {
+        Self::new(choices)
+    }
+}
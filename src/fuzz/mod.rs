@@ -0,0 +1,37 @@
+//! LibAFL components to fuzz a target kernel with command sequences.
+//!
+//! - `CommandSeqInput`: input holding a sequence of alphabet choices.
+//! - `command_seq_mutations`: mutators on command sequences.
+//! - `CheckerExecutor`: runs a sequence through `Checker` against the target.
+//! - `MismatchFeedback`: objective firing on state or return value mismatch.
+//! - `NoveltyFeedback`: feedback on new (command, retv class, state) points.
+//!
+//! Wrap the QEMU target port in `QemuSnapshotPort` to reset it between runs.
+
+mod executor;
+mod feedbacks;
+mod input;
+mod mutators;
+
+pub use executor::{CheckerExecutor, RecordingCommander};
+pub use feedbacks::{MismatchFeedback, NoveltyFeedback};
+pub use input::CommandSeqInput;
+pub use mutators::{
+    command_seq_mutations, CommandSeqMutationsType, DeleteCommandMutator, InsertCommandMutator,
+    ReplaceCommandMutator, SwapCommandsMutator,
+};
+
+use crate::{CoveragePoint, Error};
+use std::{cell::RefCell, rc::Rc};
+
+/// Outcome of the last execution of a `CheckerExecutor`.
+#[derive(Debug, Clone, Default)]
+pub struct CheckOutcome {
+    /// Check error, if the sequence failed.
+    pub error: Option<Error>,
+    /// Coverage points reached by the sequence.
+    pub points: Vec<CoveragePoint>,
+}
+
+/// Outcome shared between the executor and feedbacks.
+pub type SharedOutcome = Rc<RefCell<CheckOutcome>>;
@@ -0,0 +1,226 @@
+use super::CommandSeqInput;
+use libafl::{
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named,
+};
+use std::{borrow::Cow, num::NonZeroUsize};
+
+/// Pick a random index below `len`, `None` if `len` is zero.
+fn below<S>(state: &mut S, len: usize) -> Option<usize>
+where
+    S: HasRand,
+{
+    NonZeroUsize::new(len).map(|len| state.rand_mut().below(len))
+}
+
+/// Insert a random command at a random position.
+pub struct InsertCommandMutator {
+    name: Cow<'static, str>,
+    /// Upper bound (exclusive) of generated choices.
+    width: usize,
+    /// Maximum sequence length.
+    max_len: usize,
+}
+
+impl InsertCommandMutator {
+    /// Create a mutator generating choices below `width`, keeping sequences
+    /// within `max_len` commands.
+    pub fn new(width: usize, max_len: usize) -> Self {
+        Self {
+            name: Cow::Borrowed("InsertCommandMutator"),
+            width,
+            max_len,
+        }
+    }
+}
+
+impl Named for InsertCommandMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<CommandSeqInput, S> for InsertCommandMutator
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut CommandSeqInput,
+    ) -> Result<MutationResult, Error> {
+        if input.choices.len() >= self.max_len {
+            return Ok(MutationResult::Skipped);
+        }
+        let Some(choice) = below(state, self.width) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let pos = below(state, input.choices.len() + 1).unwrap_or(0);
+        input.choices.insert(pos, choice);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Remove a random command.
+pub struct DeleteCommandMutator {
+    name: Cow<'static, str>,
+}
+
+impl DeleteCommandMutator {
+    /// Create a new mutator.
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("DeleteCommandMutator"),
+        }
+    }
+}
+
+impl Default for DeleteCommandMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Named for DeleteCommandMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<CommandSeqInput, S> for DeleteCommandMutator
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut CommandSeqInput,
+    ) -> Result<MutationResult, Error> {
+        match below(state, input.choices.len()) {
+            Some(pos) => {
+                input.choices.remove(pos);
+                Ok(MutationResult::Mutated)
+            }
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// Replace a random command with another one.
+pub struct ReplaceCommandMutator {
+    name: Cow<'static, str>,
+    /// Upper bound (exclusive) of generated choices.
+    width: usize,
+}
+
+impl ReplaceCommandMutator {
+    /// Create a mutator generating choices below `width`.
+    pub fn new(width: usize) -> Self {
+        Self {
+            name: Cow::Borrowed("ReplaceCommandMutator"),
+            width,
+        }
+    }
+}
+
+impl Named for ReplaceCommandMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<CommandSeqInput, S> for ReplaceCommandMutator
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut CommandSeqInput,
+    ) -> Result<MutationResult, Error> {
+        let (Some(pos), Some(choice)) =
+            (below(state, input.choices.len()), below(state, self.width))
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        if input.choices[pos] == choice {
+            return Ok(MutationResult::Skipped);
+        }
+        input.choices[pos] = choice;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Swap two random commands.
+pub struct SwapCommandsMutator {
+    name: Cow<'static, str>,
+}
+
+impl SwapCommandsMutator {
+    /// Create a new mutator.
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("SwapCommandsMutator"),
+        }
+    }
+}
+
+impl Default for SwapCommandsMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Named for SwapCommandsMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Mutator<CommandSeqInput, S> for SwapCommandsMutator
+where
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut CommandSeqInput,
+    ) -> Result<MutationResult, Error> {
+        let len = input.choices.len();
+        let (Some(a), Some(b)) = (below(state, len), below(state, len)) else {
+            return Ok(MutationResult::Skipped);
+        };
+        if input.choices[a] == input.choices[b] {
+            return Ok(MutationResult::Skipped);
+        }
+        input.choices.swap(a, b);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/// Tuple type of `command_seq_mutations`.
+pub type CommandSeqMutationsType = tuple_list_type!(
+    InsertCommandMutator,
+    DeleteCommandMutator,
+    ReplaceCommandMutator,
+    SwapCommandsMutator
+);
+
+/// All command sequence mutators, to be used with a `StdScheduledMutator`.
+///
+/// `width` is the upper bound (exclusive) of generated choices, usually the
+/// largest alphabet size.
+pub fn command_seq_mutations(width: usize, max_len: usize) -> CommandSeqMutationsType {
+    tuple_list!(
+        InsertCommandMutator::new(width, max_len),
+        DeleteCommandMutator::new(),
+        ReplaceCommandMutator::new(width),
+        SwapCommandsMutator::new()
+    )
+}
@@ -0,0 +1,271 @@
+use crate::{AbstractState, Command, Commander, Error};
+use core::marker::PhantomData;
+
+/// Page size assumed by address generators.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Small deterministic random number generator (SplitMix64).
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Next random 64-bit value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Random value in `[0, n)`, 0 if `n` is 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            self.next_u64() % n
+        }
+    }
+
+    /// Return `true` with probability `1/n`.
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+
+    /// Pick an element of a non-empty slice.
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    /// Random value in `[lo, hi)`, biased towards both bounds.
+    pub fn range(&mut self, lo: i128, hi: i128) -> i128 {
+        if hi <= lo {
+            return lo;
+        }
+        // The span of the full `i128` range only fits in `u128`
+        let span = hi.wrapping_sub(lo) as u128;
+        if span == 1 {
+            return lo;
+        }
+        match self.below(4) {
+            0 => lo,
+            1 => hi - 1,
+            _ => {
+                let r = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+                lo.wrapping_add((r % span) as i128)
+            }
+        }
+    }
+
+    /// Random virtual address: page-aligned, misaligned, null, at the end of
+    /// the address space, or anywhere.
+    pub fn addr(&mut self) -> usize {
+        let page = self.below(1 << 20) as usize * PAGE_SIZE;
+        match self.below(8) {
+            0 => 0,
+            1 => usize::MAX - PAGE_SIZE + 1,
+            2 => page + 1,
+            3 => page + PAGE_SIZE - 1,
+            4 => self.next_u64() as usize,
+            _ => page,
+        }
+    }
+
+    /// Random pointer, usually null or invalid: unmapped, misaligned, kernel
+    /// space or `usize::MAX`.
+    pub fn ptr(&mut self) -> usize {
+        let addr = self.addr();
+        *self.choose(&[
+            0,
+            1,
+            PAGE_SIZE - 1,
+            usize::MAX,
+            usize::MAX - PAGE_SIZE + 1,
+            1 << (usize::BITS - 1),
+            addr,
+        ])
+    }
+
+    /// Random combination of flag bits: none, a single flag, all flags, a random
+    /// subset, or a subset with an unknown bit set.
+    pub fn flags(&mut self, flags: &[u64]) -> u64 {
+        let all = flags.iter().fold(0, |acc, f| acc | f);
+        let subset = flags
+            .iter()
+            .filter(|_| self.one_in(2))
+            .fold(0, |acc, f| acc | f);
+        match self.below(6) {
+            0 => 0,
+            1 if !flags.is_empty() => *self.choose(flags),
+            2 => all,
+            3 => {
+                let unknown = !all & (1 << self.below(64));
+                subset | unknown
+            }
+            _ => subset,
+        }
+    }
+
+    /// Random length for collections, biased towards small values.
+    pub fn length(&mut self) -> usize {
+        match self.below(8) {
+            0 => 0,
+            1 => 1,
+            2 => PAGE_SIZE,
+            _ => self.below(16) as usize,
+        }
+    }
+}
+
+/// Generate random command arguments, biased towards boundary values.
+///
+/// Use `#[derive(Generate)]` (with feature `derive`) to implement it for
+/// command structs and enums.
+pub trait Generate: Sized {
+    /// Generate a random value.
+    fn generate(rng: &mut Rng) -> Self;
+}
+
+/// Implements Generate for integer types
+macro_rules! impl_generate_int {
+    (for $($t:ty),+) => {
+        $(impl Generate for $t {
+            fn generate(rng: &mut Rng) -> Self {
+                let interesting = [
+                    0,
+                    1,
+                    <$t>::MIN,
+                    <$t>::MAX,
+                    <$t>::MAX / 2,
+                    <$t>::wrapping_sub(0, 1),
+                    (PAGE_SIZE - 1) as $t,
+                    PAGE_SIZE as $t,
+                    (PAGE_SIZE + 1) as $t,
+                ];
+                match rng.below(4) {
+                    0 => *rng.choose(&interesting),
+                    1 => <$t>::wrapping_shl(1, rng.below(<$t>::BITS as u64) as u32),
+                    2 => rng.below(256) as $t,
+                    _ => rng.next_u64() as $t,
+                }
+            }
+        })*
+    }
+}
+
+impl_generate_int!(for u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize);
+
+impl Generate for bool {
+    fn generate(rng: &mut Rng) -> Self {
+        rng.one_in(2)
+    }
+}
+
+impl Generate for char {
+    fn generate(rng: &mut Rng) -> Self {
+        *rng.choose(&['a', '/', '.', '\0', '\u{ff}', '\u{10ffff}'])
+    }
+}
+
+impl Generate for String {
+    fn generate(rng: &mut Rng) -> Self {
+        let special = ["", "/", ".", "..", "/dev/null"];
+        match rng.below(4) {
+            0 => rng.choose(&special).to_string(),
+            1 => "a".repeat(PAGE_SIZE),
+            _ => (0..rng.below(16))
+                .map(|_| (b'a' + rng.below(26) as u8) as char)
+                .collect(),
+        }
+    }
+}
+
+impl<T> Generate for Option<T>
+where
+    T: Generate,
+{
+    fn generate(rng: &mut Rng) -> Self {
+        if rng.one_in(4) {
+            None
+        } else {
+            Some(T::generate(rng))
+        }
+    }
+}
+
+impl<T> Generate for Vec<T>
+where
+    T: Generate,
+{
+    fn generate(rng: &mut Rng) -> Self {
+        (0..rng.length()).map(|_| T::generate(rng)).collect()
+    }
+}
+
+impl<T, const N: usize> Generate for [T; N]
+where
+    T: Generate,
+{
+    fn generate(rng: &mut Rng) -> Self {
+        core::array::from_fn(|_| T::generate(rng))
+    }
+}
+
+/// Commander that yields randomly generated commands of type `C`.
+pub struct RandomCommander<C> {
+    rng: Rng,
+    phantom: PhantomData<C>,
+}
+
+impl<C> RandomCommander<C> {
+    /// Create a commander with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, S> Commander<S> for RandomCommander<C>
+where
+    C: Command<S> + Generate + 'static,
+    S: AbstractState,
+{
+    fn command(&mut self, _state: &S) -> Result<Box<dyn Command<S>>, Error> {
+        Ok(Box::new(C::generate(&mut self.rng)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut rng = Rng::new(0);
+        let bounds = [
+            (i128::MIN, i128::MAX),
+            (i128::MIN, 0),
+            (-1, i128::MAX),
+            (0, u64::MAX as i128 + 1),
+            (-3, 4),
+            (i128::MAX - 1, i128::MAX),
+        ];
+        for (lo, hi) in bounds {
+            for _ in 0..1000 {
+                let x = rng.range(lo, hi);
+                assert!(lo <= x && x < hi, "{} not in [{}, {})", x, lo, hi);
+            }
+        }
+        assert_eq!(rng.range(i128::MAX, i128::MAX), i128::MAX);
+        assert_eq!(rng.range(5, 2), 5);
+    }
+}
@@ -1,22 +1,52 @@
+mod bucket;
+mod campaign;
 mod checker;
 mod command;
+mod coverage;
 mod error;
+mod explore;
+mod generate;
 mod mem;
 mod port;
 mod printer;
+mod shrink;
+mod suppress;
 
 pub mod state;
 
-pub use checker::{CheckLevel, Checker};
-pub use command::{Command, Commander};
+pub use bucket::{normalize_path, Bucket, Buckets, Signature};
+pub use campaign::{
+    load_trace, Campaign, CampaignFailure, CampaignReport, Corpus, CorpusCommander,
+};
+pub use checker::{CheckLevel, CheckPolicy, Checker};
+pub use command::{
+    split_id, Category, Command, CommandId, CommandKind, CommandRegistry, Commander, Decode,
+    DecodeFn, Encode, Feedback, FromBytes, ReplayCommander, ID_LEN,
+};
+pub use coverage::{Coverage, CoveragePoint, NoveltyCommander, RetvClass};
 pub use error::Error;
+pub use explore::{
+    AlphabetCommander, BoundedExplorer, CommandAlphabet, Exploration, ExplorationFailure,
+    ModelExplorer, ModelPanic, StateGraph, Transition,
+};
+pub use generate::{Generate, RandomCommander, Rng, PAGE_SIZE};
 pub use mem::{ReadTargetMem, WriteTargetMem};
-pub use port::{CommandChannel, MemCommandChannel, MockTestPort, StateChannel, TestPort};
+pub use port::{
+    CommandChannel, MemCommandChannel, MockTestPort, ResetTarget, RestoreTarget, StateChannel,
+    TestPort,
+};
 pub use printer::{Printer, StdoutPrinter};
+pub use shrink::{check_sequence, shrink_sequence, Shrink};
 pub use state::AbstractState;
+pub use suppress::{Mismatch, Suppression, Suppressions};
 
 #[cfg(feature = "derive")]
 pub use km_derive::*;
 
 #[cfg(feature = "qemu")]
 pub use mem::QemuMem;
+#[cfg(feature = "qemu")]
+pub use port::QemuSnapshotPort;
+
+#[cfg(feature = "qemu")]
+pub mod fuzz;
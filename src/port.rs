@@ -65,8 +65,28 @@ where
 {
 }
 
+/// Trait for taking snapshots of a test target and rolling back to them.
+pub trait RestoreTarget {
+    /// Saved target state.
+    type Snapshot;
+
+    /// Take a snapshot of the current target state.
+    fn snapshot(&mut self) -> Result<Self::Snapshot, Error>;
+
+    /// Roll the target back to a previously taken snapshot.
+    fn restore(&mut self, snapshot: &Self::Snapshot) -> Result<(), Error>;
+}
+
+/// Trait for restarting a test target from its initial state.
+pub trait ResetTarget {
+    /// Reset the target to its initial state.
+    fn reset(&mut self) -> Result<(), Error>;
+}
+
 /// A mock implementation of `TestPort` that emulates a test target using an internal state.
 pub struct MockTestPort<S> {
+    /// State of the first state retrieval, restored by `reset`.
+    initial: Option<S>,
     state: S,
     result: isize,
 }
@@ -74,7 +94,11 @@ pub struct MockTestPort<S> {
 impl<S> MockTestPort<S> {
     /// Create a new mock test port with the given initial state.
     pub fn new(state: S) -> Self {
-        Self { state, result: 0 }
+        Self {
+            initial: None,
+            state,
+            result: 0,
+        }
     }
 }
 
@@ -102,12 +126,47 @@ where
         Ok(true)
     }
     fn finish_state_retrieval(&mut self) -> Result<S, Error> {
+        if self.initial.is_none() {
+            self.initial = Some(self.state.clone());
+        }
         Ok(self.state.clone())
     }
 }
 
 impl<S> TestPort<S> for MockTestPort<S> where S: AbstractState + Clone {}
 
+impl<S> RestoreTarget for MockTestPort<S>
+where
+    S: Clone,
+{
+    /// State and return value of the last command.
+    type Snapshot = (S, isize);
+
+    fn snapshot(&mut self) -> Result<(S, isize), Error> {
+        Ok((self.state.clone(), self.result))
+    }
+    fn restore(&mut self, snapshot: &(S, isize)) -> Result<(), Error> {
+        self.state = snapshot.0.clone();
+        self.result = snapshot.1;
+        Ok(())
+    }
+}
+
+impl<S> ResetTarget for MockTestPort<S>
+where
+    S: Clone,
+{
+    /// Go back to the state of the first state retrieval, i.e. the initial
+    /// state seen by a checker.
+    fn reset(&mut self) -> Result<(), Error> {
+        if let Some(initial) = &self.initial {
+            self.state = initial.clone();
+        }
+        self.result = 0;
+        Ok(())
+    }
+}
+
 /// Facilitates sending commands and receiving results via the target's virtual memory.
 pub struct MemCommandChannel<R, W> {
     reader: R,
@@ -157,3 +216,137 @@ where
         Ok(buf)
     }
 }
+
+#[cfg(feature = "qemu")]
+pub use qemu::QemuSnapshotPort;
+
+#[cfg(feature = "qemu")]
+mod qemu {
+    use super::{CommandChannel, ResetTarget, StateChannel, TestPort};
+    use crate::{AbstractState, Command, Error};
+    use libafl_qemu::{FastSnapshotPtr, Qemu};
+
+    /// Test port of a QEMU target that is reset by restoring a QEMU snapshot.
+    ///
+    /// The snapshot is taken when the port is created, so create it once the
+    /// target kernel is booted and ready to receive commands. Commands and
+    /// states go through the inner port.
+    pub struct QemuSnapshotPort<T> {
+        inner: T,
+        qemu: Qemu,
+        snapshot: FastSnapshotPtr,
+    }
+
+    impl<T> QemuSnapshotPort<T> {
+        /// Snapshot the running QEMU instance and wrap `inner`.
+        ///
+        /// `Io` if QEMU is not initialized.
+        pub fn new(inner: T) -> Result<Self, Error> {
+            let qemu = Qemu::get().ok_or(Error::Io)?;
+            let snapshot = qemu.create_fast_snapshot(true);
+            Ok(Self {
+                inner,
+                qemu,
+                snapshot,
+            })
+        }
+
+        /// Get the inner port.
+        pub fn inner(&mut self) -> &mut T {
+            &mut self.inner
+        }
+    }
+
+    impl<T> Drop for QemuSnapshotPort<T> {
+        fn drop(&mut self) {
+            unsafe { libafl_qemu::sys::syx_snapshot_free(self.snapshot) };
+        }
+    }
+
+    impl<T> ResetTarget for QemuSnapshotPort<T> {
+        fn reset(&mut self) -> Result<(), Error> {
+            unsafe { self.qemu.restore_fast_snapshot(self.snapshot) };
+            Ok(())
+        }
+    }
+
+    impl<S, T> CommandChannel<S> for QemuSnapshotPort<T>
+    where
+        S: AbstractState,
+        T: CommandChannel<S>,
+    {
+        fn send_command(&mut self, command: &dyn Command<S>) -> Result<(), Error> {
+            self.inner.send_command(command)
+        }
+        fn receive_retv(&mut self) -> isize {
+            self.inner.receive_retv()
+        }
+        fn receive_extra_data(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+            self.inner.receive_extra_data(len)
+        }
+    }
+
+    impl<S, T> StateChannel<S> for QemuSnapshotPort<T>
+    where
+        S: AbstractState,
+        T: StateChannel<S>,
+    {
+        fn start_state_retrieval(&mut self) -> Result<(), Error> {
+            self.inner.start_state_retrieval()
+        }
+        fn retrieve_state_data(&mut self) -> Result<bool, Error> {
+            self.inner.retrieve_state_data()
+        }
+        fn finish_state_retrieval(&mut self) -> Result<S, Error> {
+            self.inner.finish_state_retrieval()
+        }
+    }
+
+    impl<S, T> TestPort<S> for QemuSnapshotPort<T>
+    where
+        S: AbstractState,
+        T: TestPort<S>,
+    {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Value;
+
+    #[derive(Debug)]
+    struct Add(u8);
+
+    impl Command<Value<u8>> for Add {
+        fn execute(&self, state: &mut Value<u8>) -> isize {
+            state.0 += self.0;
+            state.0 as isize
+        }
+        fn to_bytes(&self) -> Vec<u8> {
+            vec![self.0]
+        }
+    }
+
+    #[test]
+    fn mock_snapshot_keeps_retv() {
+        let mut port = MockTestPort::new(Value(0u8));
+        port.send_command(&Add(1)).unwrap();
+        let snapshot = port.snapshot().unwrap();
+        port.send_command(&Add(2)).unwrap();
+        assert_eq!(port.receive_retv(), 3);
+        port.restore(&snapshot).unwrap();
+        assert_eq!(port.receive_retv(), 1);
+        assert_eq!(port.finish_state_retrieval().unwrap().0, 1);
+    }
+
+    #[test]
+    fn mock_reset_to_first_retrieval() {
+        let mut port = MockTestPort::new(Value(5u8));
+        assert_eq!(port.finish_state_retrieval().unwrap().0, 5);
+        port.send_command(&Add(1)).unwrap();
+        port.reset().unwrap();
+        assert_eq!(port.receive_retv(), 0);
+        assert_eq!(port.finish_state_retrieval().unwrap().0, 5);
+    }
+}
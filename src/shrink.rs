@@ -0,0 +1,286 @@
+use crate::{
+    port::TestPort, AbstractState, CheckLevel, Checker, Command, Error, Printer, ReplayCommander,
+};
+use core::fmt::Debug;
+
+/// Produce simpler variants of a command argument, used to minimize failing
+/// commands.
+///
+/// Candidates are ordered from the most to the least aggressive and must be
+/// strictly simpler than the value by a well-founded measure, otherwise
+/// `shrink_sequence` may not terminate. Use `#[derive(Shrink)]` (with feature `derive`) to
+/// implement it for command structs and enums.
+pub trait Shrink: Sized {
+    /// Simpler candidates of self.
+    fn shrink(&self) -> Vec<Self>;
+}
+
+/// Implements Shrink for unsigned integer types: towards zero, and with fewer
+/// bits set
+macro_rules! impl_shrink_unsigned {
+    (for $($t:ty),+) => {
+        $(impl Shrink for $t {
+            fn shrink(&self) -> Vec<Self> {
+                let x = *self;
+                let towards_zero = [0, x / 2, x.saturating_sub(1)];
+                // Clear set bits one at a time, from the highest.
+                let fewer_bits = (0..<$t>::BITS).rev().map(|b| x & !(1 << b));
+                let mut res = Vec::new();
+                for v in towards_zero.into_iter().chain(fewer_bits) {
+                    if v != x && !res.contains(&v) {
+                        res.push(v);
+                    }
+                }
+                res
+            }
+        })*
+    }
+}
+
+/// Implements Shrink for signed integer types
+///
+/// Every candidate is smaller than the value by absolute value, then by sign
+/// (positive is simpler), then by number of set bits, so shrinking terminates.
+/// Bits of negative values are never cleared, since that can grow their
+/// absolute value (`-1` becomes `-2`).
+macro_rules! impl_shrink_signed {
+    (for $($t:ty),+) => {
+        $(impl Shrink for $t {
+            fn shrink(&self) -> Vec<Self> {
+                let x = *self;
+                let candidates: Vec<$t> = if x < 0 {
+                    [Some(0), x.checked_neg(), Some(x / 2), Some(x + 1)]
+                        .into_iter()
+                        .flatten()
+                        .collect()
+                } else {
+                    // Clear set bits one at a time, from the highest.
+                    [0, x / 2, (x - 1).max(0)]
+                        .into_iter()
+                        .chain((0..<$t>::BITS - 1).rev().map(|b| x & !(1 << b)))
+                        .collect()
+                };
+                let mut res = Vec::new();
+                for v in candidates {
+                    if v != x && !res.contains(&v) {
+                        res.push(v);
+                    }
+                }
+                res
+            }
+        })*
+    }
+}
+
+impl_shrink_unsigned!(for u8, u16, u32, u64, u128, usize);
+impl_shrink_signed!(for i8, i16, i32, i64, i128, isize);
+
+impl Shrink for bool {
+    fn shrink(&self) -> Vec<Self> {
+        if *self {
+            vec![false]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl Shrink for char {
+    fn shrink(&self) -> Vec<Self> {
+        if *self == 'a' {
+            Vec::new()
+        } else {
+            vec!['a']
+        }
+    }
+}
+
+impl Shrink for String {
+    fn shrink(&self) -> Vec<Self> {
+        let chars: Vec<char> = self.chars().collect();
+        chars
+            .shrink()
+            .into_iter()
+            .map(|c| c.into_iter().collect())
+            .collect()
+    }
+}
+
+impl<T> Shrink for Vec<T>
+where
+    T: Shrink + Clone,
+{
+    fn shrink(&self) -> Vec<Self> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let mut res = vec![Vec::new()];
+        if self.len() > 2 {
+            res.push(self[..self.len() / 2].to_vec());
+        }
+        if self.len() > 1 {
+            res.extend((0..self.len()).map(|i| {
+                let mut v = self.clone();
+                v.remove(i);
+                v
+            }));
+        }
+        for (i, item) in self.iter().enumerate() {
+            res.extend(item.shrink().into_iter().map(|s| {
+                let mut v = self.clone();
+                v[i] = s;
+                v
+            }));
+        }
+        res
+    }
+}
+
+impl<T, const N: usize> Shrink for [T; N]
+where
+    T: Shrink + Clone,
+{
+    fn shrink(&self) -> Vec<Self> {
+        let mut res = Vec::new();
+        for (i, item) in self.iter().enumerate() {
+            res.extend(item.shrink().into_iter().map(|s| {
+                let mut v = self.clone();
+                v[i] = s;
+                v
+            }));
+        }
+        res
+    }
+}
+
+impl<T> Shrink for Option<T>
+where
+    T: Shrink,
+{
+    fn shrink(&self) -> Vec<Self> {
+        match self {
+            Some(v) => core::iter::once(None)
+                .chain(v.shrink().into_iter().map(Some))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Minimize a failing command sequence.
+///
+/// `fails` runs a candidate sequence and returns `true` if the failure still
+/// reproduces; it should also check the failure is the same one (e.g. same
+/// command kind and error). The sequence is first shortened by removing chunks
+/// of commands, then each command is shrunk argument by argument.
+pub fn shrink_sequence<C, F>(mut commands: Vec<C>, mut fails: F) -> Vec<C>
+where
+    C: Shrink + Clone,
+    F: FnMut(&[C]) -> bool,
+{
+    // Remove chunks of commands, halving the chunk size down to single commands.
+    let mut chunk = commands.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < commands.len() {
+            let end = (start + chunk).min(commands.len());
+            let candidate: Vec<C> = commands[..start]
+                .iter()
+                .chain(&commands[end..])
+                .cloned()
+                .collect();
+            if !candidate.is_empty() && fails(&candidate) {
+                commands = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+    // Shrink arguments until no candidate reproduces the failure.
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for i in 0..commands.len() {
+            for candidate in commands[i].shrink() {
+                let saved = core::mem::replace(&mut commands[i], candidate);
+                if fails(&commands) {
+                    progress = true;
+                    break;
+                }
+                commands[i] = saved;
+            }
+        }
+    }
+    commands
+}
+
+/// Check a command sequence from the start on a fresh target.
+///
+/// Return the first check error, convenient as the oracle of `shrink_sequence`.
+pub fn check_sequence<C, T, P, S>(
+    commands: &[C],
+    port: T,
+    printer: P,
+    state: S,
+    retv_level: CheckLevel,
+    state_level: CheckLevel,
+) -> Result<(), Error>
+where
+    C: Command<S> + Clone + 'static,
+    T: TestPort<S>,
+    P: Printer,
+    S: AbstractState + Debug,
+{
+    let commands = commands
+        .iter()
+        .map(|c| Box::new(c.clone()) as Box<dyn Command<S>>)
+        .collect();
+    let mut checker = Checker::new(ReplayCommander::new(commands), port, printer, state);
+    checker.run_round(retv_level, state_level)?;
+    while checker.commander().remaining() > 0 {
+        checker.run_round(retv_level, state_level)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Well-founded measure of integers: absolute value, sign, set bits.
+    fn measure(x: i64) -> (u64, bool, u32) {
+        (x.unsigned_abs(), x < 0, x.count_ones())
+    }
+
+    #[test]
+    fn signed_candidates_are_simpler() {
+        let values = (-300..300).chain([i64::MIN, i64::MIN + 1, i64::MAX]);
+        for x in values {
+            for c in x.shrink() {
+                assert!(measure(c) < measure(x), "{} shrinks to {}", x, c);
+            }
+        }
+    }
+
+    #[test]
+    fn unsigned_candidates_are_smaller() {
+        for x in (0..300u64).chain([u64::MAX]) {
+            assert!(x.shrink().iter().all(|&c| c < x));
+        }
+    }
+
+    #[test]
+    fn shrink_terminates_on_negative_values() {
+        let res = shrink_sequence(vec![-1i64], |c| c[0] == -1 || c[0] == -2);
+        assert_eq!(res, vec![-1]);
+        let res = shrink_sequence(vec![-100i32], |c| c[0] < -10);
+        assert_eq!(res, vec![-11]);
+    }
+
+    #[test]
+    fn shrink_sequence_minimizes() {
+        let res = shrink_sequence(vec![7u8, 200, 3, 9], |c| c.iter().any(|&x| x >= 100));
+        assert_eq!(res, vec![100]);
+    }
+}
@@ -0,0 +1,302 @@
+use super::{
+    fingerprint, join_path, key_of, ordered_key, split_index, unordered_key, AbstractState,
+};
+use crate::CheckLevel;
+use core::fmt::Debug;
+use core::hash::Hash;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+/// Implements AbstractState for sequences, checked element by element
+macro_rules! impl_AbstractState_seq {
+    (for $($t:ident),+) => {
+        $(impl<T> AbstractState for $t<T>
+        where
+            T: AbstractState + Clone,
+        {
+            fn matches(&self, other: &Self) -> bool {
+                self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a.matches(b))
+            }
+            /// Update element by element if lengths are equal, otherwise clone.
+            fn update(&mut self, other: &Self) {
+                if self.len() == other.len() {
+                    self.iter_mut().zip(other.iter()).for_each(|(a, b)| a.update(b));
+                } else {
+                    *self = other.clone();
+                }
+            }
+            fn diff(&self, other: &Self) -> Vec<String> {
+                if self.len() != other.len() {
+                    return vec![String::new()];
+                }
+                let mut diffs = Vec::new();
+                for (i, (a, b)) in self.iter().zip(other.iter()).enumerate() {
+                    let prefix = format!("[{}]", i);
+                    diffs.extend(a.diff(b).iter().map(|p| join_path(&prefix, p)));
+                }
+                diffs
+            }
+            fn level(&self, path: &str) -> Option<CheckLevel> {
+                let (index, rest) = split_index(path)?;
+                self.get(index.parse::<usize>().ok()?)?.level(rest)
+            }
+            fn learn(&mut self, other: &Self) {
+                if self.len() == other.len() {
+                    self.iter_mut().zip(other.iter()).for_each(|(a, b)| a.learn(b));
+                }
+            }
+            fn key(&self) -> u64 {
+                ordered_key(self.iter().map(T::key))
+            }
+        })*
+    }
+}
+
+impl_AbstractState_seq!(for Vec, VecDeque);
+
+impl<T, const N: usize> AbstractState for [T; N]
+where
+    T: AbstractState,
+{
+    fn matches(&self, other: &Self) -> bool {
+        self.iter().zip(other.iter()).all(|(a, b)| a.matches(b))
+    }
+    fn update(&mut self, other: &Self) {
+        self.iter_mut()
+            .zip(other.iter())
+            .for_each(|(a, b)| a.update(b));
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        for (i, (a, b)) in self.iter().zip(other.iter()).enumerate() {
+            let prefix = format!("[{}]", i);
+            diffs.extend(a.diff(b).iter().map(|p| join_path(&prefix, p)));
+        }
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (index, rest) = split_index(path)?;
+        self.get(index.parse::<usize>().ok()?)?.level(rest)
+    }
+    fn learn(&mut self, other: &Self) {
+        self.iter_mut()
+            .zip(other.iter())
+            .for_each(|(a, b)| a.learn(b));
+    }
+    fn key(&self) -> u64 {
+        ordered_key(self.iter().map(T::key))
+    }
+}
+
+impl<T> AbstractState for Box<T>
+where
+    T: AbstractState + ?Sized,
+{
+    fn matches(&self, other: &Self) -> bool {
+        (**self).matches(other)
+    }
+    fn update(&mut self, other: &Self) {
+        (**self).update(other)
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        (**self).diff(other)
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        (**self).level(path)
+    }
+    fn learn(&mut self, other: &Self) {
+        (**self).learn(other)
+    }
+    fn key(&self) -> u64 {
+        (**self).key()
+    }
+}
+
+/// Implements AbstractState for tuples, checked field by field
+macro_rules! impl_AbstractState_tuple {
+    ($(($($T:ident $i:tt),+)),+) => {
+        $(impl<$($T),+> AbstractState for ($($T,)+)
+        where
+            $($T: AbstractState,)+
+        {
+            fn matches(&self, other: &Self) -> bool {
+                $(self.$i.matches(&other.$i))&&+
+            }
+            fn update(&mut self, other: &Self) {
+                $(self.$i.update(&other.$i);)+
+            }
+            fn diff(&self, other: &Self) -> Vec<String> {
+                let mut diffs = Vec::new();
+                $(diffs.extend(
+                    self.$i.diff(&other.$i).iter().map(|p| join_path(stringify!($i), p)),
+                );)+
+                diffs
+            }
+            fn level(&self, path: &str) -> Option<CheckLevel> {
+                $(if let Some(rest) = super::strip_path(path, stringify!($i)) {
+                    return self.$i.level(rest);
+                })+
+                None
+            }
+            fn learn(&mut self, other: &Self) {
+                $(self.$i.learn(&other.$i);)+
+            }
+            fn key(&self) -> u64 {
+                ordered_key([$(self.$i.key()),+])
+            }
+        })+
+    }
+}
+
+impl_AbstractState_tuple!(
+    (A 0),
+    (A 0, B 1),
+    (A 0, B 1, C 2),
+    (A 0, B 1, C 2, D 3),
+    (A 0, B 1, C 2, D 3, E 4),
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+);
+
+/// Implements AbstractState for sets, checked by equality of elements
+///
+/// Elements are identified by their `Debug` form in paths and keys, hence the
+/// bound. `ValueSet` identifies them by position and doesn't need it.
+macro_rules! impl_AbstractState_set {
+    (for $($t:ident: $($bound:path),+);+) => {
+        $(impl<T> AbstractState for $t<T>
+        where
+            T: Clone + Debug $(+ $bound)+,
+        {
+            fn matches(&self, other: &Self) -> bool {
+                self == other
+            }
+            fn update(&mut self, other: &Self) {
+                self.clone_from(other);
+            }
+            /// One path `[element]` per element in only one of the sets.
+            fn diff(&self, other: &Self) -> Vec<String> {
+                let mut diffs: Vec<String> = self
+                    .symmetric_difference(other)
+                    .map(|e| format!("[{:?}]", e))
+                    .collect();
+                diffs.sort();
+                diffs
+            }
+            fn key(&self) -> u64 {
+                unordered_key(self.iter().map(fingerprint))
+            }
+        })+
+    }
+}
+
+impl_AbstractState_set!(for BTreeSet: Ord; HashSet: Eq, Hash);
+
+/// Implements AbstractState for maps, keys are checked by equality
+///
+/// Keys are identified by their `Debug` form in paths and state keys, hence
+/// the bound. `ValueMap` identifies them by position and doesn't need it.
+macro_rules! impl_AbstractState_map {
+    (for $($t:ident: $($bound:path),+);+) => {
+        $(impl<K, V> AbstractState for $t<K, V>
+        where
+            K: Clone + Debug $(+ $bound)+,
+            V: AbstractState + Clone,
+        {
+            fn matches(&self, other: &Self) -> bool {
+                self.len() == other.len()
+                    && self
+                        .iter()
+                        .all(|(k, v)| other.get(k).is_some_and(|ov| v.matches(ov)))
+            }
+            /// Update values of common keys, take added and removed keys.
+            fn update(&mut self, other: &Self) {
+                self.retain(|k, _| other.contains_key(k));
+                for (k, ov) in other {
+                    match self.get_mut(k) {
+                        Some(v) => v.update(ov),
+                        None => {
+                            self.insert(k.clone(), ov.clone());
+                        }
+                    }
+                }
+            }
+            fn diff(&self, other: &Self) -> Vec<String> {
+                let mut diffs = Vec::new();
+                for (k, v) in self {
+                    let prefix = format!("[{:?}]", k);
+                    match other.get(k) {
+                        Some(ov) => diffs.extend(v.diff(ov).iter().map(|p| join_path(&prefix, p))),
+                        None => diffs.push(prefix),
+                    }
+                }
+                for k in other.keys().filter(|k| !self.contains_key(k)) {
+                    diffs.push(format!("[{:?}]", k));
+                }
+                diffs.sort();
+                diffs
+            }
+            fn level(&self, path: &str) -> Option<CheckLevel> {
+                let (key, rest) = split_index(path)?;
+                self.iter().find(|(k, _)| format!("{:?}", k) == key)?.1.level(rest)
+            }
+            fn learn(&mut self, other: &Self) {
+                for (k, v) in self.iter_mut() {
+                    if let Some(ov) = other.get(k) {
+                        v.learn(ov);
+                    }
+                }
+            }
+            fn key(&self) -> u64 {
+                unordered_key(self.iter().map(|(k, v)| key_of(&(fingerprint(k), v.key()))))
+            }
+        })+
+    }
+}
+
+impl_AbstractState_map!(for BTreeMap: Ord; HashMap: Eq, Hash);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Value;
+
+    #[test]
+    fn map_paths_are_keys() {
+        let model = HashMap::from([("a", Value(1)), ("b", Value(2)), ("c", Value(3))]);
+        let mut target = model.clone();
+        assert!(target.matches(&model));
+        target.insert("b", Value(5));
+        target.remove("c");
+        target.insert("d", Value(3));
+        assert!(!target.matches(&model));
+        assert_eq!(
+            model.diff(&target),
+            vec![r#"["b"]"#, r#"["c"]"#, r#"["d"]"#]
+        );
+        let mut model = model;
+        model.update(&target);
+        assert!(target.matches(&model));
+    }
+
+    #[test]
+    fn set_paths_are_elements() {
+        let model = BTreeSet::from([1, 2, 3]);
+        let target = BTreeSet::from([1, 3, 4]);
+        assert!(!target.matches(&model));
+        assert_eq!(model.diff(&target), vec!["[2]", "[4]"]);
+        assert!(model.diff(&BTreeSet::from([3, 2, 1])).is_empty());
+    }
+
+    #[test]
+    fn arrays_and_tuples() {
+        let model = [Value(1), Value(2)];
+        assert!([Value(1), Value(2)].matches(&model));
+        assert_eq!(model.diff(&[Value(1), Value(3)]), vec!["[1]"]);
+        let model = (Value(1), [Value(2), Value(3)], Some(Value(4)));
+        let target = (Value(0), [Value(2), Value(0)], Some(Value(4)));
+        assert!(!target.matches(&model));
+        assert_eq!(model.diff(&target), vec!["0", "1[1]"]);
+        let mut model = model;
+        model.update(&target);
+        assert!(target.matches(&model));
+    }
+}
@@ -2,6 +2,8 @@ use super::AbstractState;
 use core::ops::{Deref, DerefMut};
 
 /// Mark a field as not-checked and not-updated.
+///
+/// It matches anything, so it keeps the default key.
 #[derive(Debug, Clone, Default)]
 pub struct Ignored<T>(pub T);
 
@@ -1,4 +1,5 @@
-use super::AbstractState;
+use super::{join_path, key_of, strip_path, AbstractState};
+use crate::CheckLevel;
 
 /// A common interval type.
 #[derive(Debug, Clone, Copy, Default)]
@@ -23,6 +24,31 @@ where
         self.right = other.right;
         self.value.update(&other.value);
     }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if self.left != other.left {
+            diffs.push("left".to_string());
+        }
+        if self.right != other.right {
+            diffs.push("right".to_string());
+        }
+        diffs.extend(
+            self.value
+                .diff(&other.value)
+                .iter()
+                .map(|p| join_path("value", p)),
+        );
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        self.value.level(strip_path(path, "value")?)
+    }
+    fn learn(&mut self, other: &Self) {
+        self.value.learn(&other.value);
+    }
+    fn key(&self) -> u64 {
+        key_of(&(self.left, self.right, self.value.key()))
+    }
 }
 
 impl<T> Interval<T> {
@@ -0,0 +1,400 @@
+use super::{join_path, ordered_key, split_index, AbstractState, Interval};
+use crate::CheckLevel;
+use std::collections::BTreeMap;
+
+/// Sorted non-overlapping intervals with associated values, e.g. the VMAs of
+/// an address space.
+///
+/// Adjacent intervals with equal values are merged, so two maps covering the
+/// same ranges with the same values have the same intervals. Intervals are
+/// identified by their left bound in state paths, e.g. `[4096].value`.
+#[derive(Debug, Clone, Default)]
+pub struct IntervalMap<T> {
+    intervals: BTreeMap<usize, Interval<T>>,
+}
+
+impl<T> IntervalMap<T> {
+    pub fn new() -> Self {
+        Self {
+            intervals: BTreeMap::new(),
+        }
+    }
+    /// Number of intervals.
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+    /// Check if no interval is present.
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+    /// Iterate over intervals in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &Interval<T>> {
+        self.intervals.values()
+    }
+    /// Get the interval containing a point.
+    pub fn get(&self, point: usize) -> Option<&Interval<T>> {
+        self.intervals
+            .range(..=point)
+            .next_back()
+            .map(|(_, i)| i)
+            .filter(|i| i.contains(point))
+    }
+    /// Check if a point is covered by an interval.
+    pub fn contains(&self, point: usize) -> bool {
+        self.get(point).is_some()
+    }
+    /// Iterate over intervals overlapping `[left, right)`.
+    pub fn overlapping(&self, left: usize, right: usize) -> impl Iterator<Item = &Interval<T>> {
+        // Only the interval starting before `left` may reach into the range
+        let start = self
+            .intervals
+            .range(..left)
+            .next_back()
+            .filter(|(_, i)| i.right > left)
+            .map_or(left, |(&l, _)| l);
+        self.intervals
+            .range(start..right.max(start))
+            .map(|(_, i)| i)
+    }
+    /// Check if `[left, right)` is fully covered by intervals.
+    pub fn covers(&self, left: usize, right: usize) -> bool {
+        self.gaps(left, right).is_empty()
+    }
+    /// Uncovered parts of `[left, right)`, in ascending order.
+    pub fn gaps(&self, left: usize, right: usize) -> Vec<(usize, usize)> {
+        let mut gaps = Vec::new();
+        let mut cur = left;
+        for i in self.overlapping(left, right) {
+            if i.left > cur {
+                gaps.push((cur, i.left));
+            }
+            cur = cur.max(i.right);
+        }
+        if cur < right {
+            gaps.push((cur, right));
+        }
+        gaps
+    }
+    /// Find the lowest uncovered range of `size` within `[left, right)`,
+    /// returning its left bound.
+    pub fn find_gap(&self, size: usize, left: usize, right: usize) -> Option<usize> {
+        self.gaps(left, right)
+            .into_iter()
+            .find(|(l, r)| r - l >= size)
+            .map(|(l, _)| l)
+    }
+}
+
+impl<T> IntervalMap<T>
+where
+    T: Clone + PartialEq,
+{
+    /// Split the interval containing `point` at `point`.
+    fn split(&mut self, point: usize) {
+        if let Some(i) = self
+            .intervals
+            .range_mut(..point)
+            .next_back()
+            .map(|(_, i)| i)
+        {
+            if i.right > point {
+                let right = Interval::new(point, i.right, i.value.clone());
+                i.right = point;
+                self.intervals.insert(point, right);
+            }
+        }
+    }
+    /// Merge the interval starting at `point` with its left neighbour if they
+    /// are adjacent and have equal values.
+    fn merge(&mut self, point: usize) {
+        let Some(right) = self.intervals.get(&point) else {
+            return;
+        };
+        let Some((_, left)) = self.intervals.range(..point).next_back() else {
+            return;
+        };
+        if left.right == point && left.value == right.value {
+            let right = self.intervals.remove(&point).unwrap().right;
+            self.intervals
+                .range_mut(..point)
+                .next_back()
+                .unwrap()
+                .1
+                .right = right;
+        }
+    }
+    /// Map `[left, right)` to `value`, splitting or replacing intervals
+    /// overlapping it.
+    pub fn insert(&mut self, left: usize, right: usize, value: T) {
+        if left >= right {
+            return;
+        }
+        self.remove(left, right);
+        self.intervals
+            .insert(left, Interval::new(left, right, value));
+        self.merge(right);
+        self.merge(left);
+    }
+    /// Unmap `[left, right)`, splitting intervals overlapping it. Return the
+    /// removed parts.
+    pub fn remove(&mut self, left: usize, right: usize) -> Vec<Interval<T>> {
+        if left >= right {
+            return Vec::new();
+        }
+        self.split(left);
+        self.split(right);
+        let keys: Vec<usize> = self.intervals.range(left..right).map(|(&l, _)| l).collect();
+        keys.into_iter()
+            .filter_map(|l| self.intervals.remove(&l))
+            .collect()
+    }
+    /// Apply `f` to the values of the covered parts of `[left, right)`, e.g.
+    /// to change the protection of part of a mapping.
+    pub fn modify<F>(&mut self, left: usize, right: usize, mut f: F)
+    where
+        F: FnMut(&mut T),
+    {
+        if left >= right {
+            return;
+        }
+        self.split(left);
+        self.split(right);
+        let keys: Vec<usize> = self.intervals.range(left..right).map(|(&l, _)| l).collect();
+        for &l in &keys {
+            f(&mut self.intervals.get_mut(&l).unwrap().value);
+        }
+        self.merge(right);
+        for &l in keys.iter().rev() {
+            self.merge(l);
+        }
+    }
+}
+
+impl<T> AbstractState for IntervalMap<T>
+where
+    T: AbstractState + Clone,
+{
+    fn matches(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a.matches(b))
+    }
+    /// Update values of identical intervals in place if the intervals are the
+    /// same, otherwise clone.
+    fn update(&mut self, other: &Self) {
+        let same = self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.left == b.left && a.right == b.right);
+        if same {
+            self.intervals
+                .values_mut()
+                .zip(other.iter())
+                .for_each(|(a, b)| a.value.update(&b.value));
+        } else {
+            *self = other.clone();
+        }
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        for (l, i) in &self.intervals {
+            let prefix = format!("[{}]", l);
+            match other.intervals.get(l) {
+                Some(oi) => diffs.extend(i.diff(oi).iter().map(|p| join_path(&prefix, p))),
+                None => diffs.push(prefix),
+            }
+        }
+        for l in other.intervals.keys() {
+            if !self.intervals.contains_key(l) {
+                diffs.push(format!("[{}]", l));
+            }
+        }
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (left, rest) = split_index(path)?;
+        self.intervals.get(&left.parse().ok()?)?.level(rest)
+    }
+    fn learn(&mut self, other: &Self) {
+        for (l, i) in self.intervals.iter_mut() {
+            if let Some(oi) = other.intervals.get(l).filter(|oi| oi.right == i.right) {
+                i.value.learn(&oi.value);
+            }
+        }
+    }
+    fn key(&self) -> u64 {
+        ordered_key(self.iter().map(Interval::key))
+    }
+}
+
+/// Sorted non-overlapping intervals, e.g. the free ranges of an allocator.
+///
+/// Adjacent intervals are merged. Intervals are identified by their left
+/// bound in state paths.
+#[derive(Debug, Clone, Default)]
+pub struct IntervalSet(IntervalMap<()>);
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self(IntervalMap::new())
+    }
+    /// Number of intervals.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    /// Check if no interval is present.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Iterate over `(left, right)` of intervals in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.0.iter().map(|i| (i.left, i.right))
+    }
+    /// Get the interval containing a point.
+    pub fn get(&self, point: usize) -> Option<(usize, usize)> {
+        self.0.get(point).map(|i| (i.left, i.right))
+    }
+    /// Check if a point is covered by an interval.
+    pub fn contains(&self, point: usize) -> bool {
+        self.0.contains(point)
+    }
+    /// Iterate over intervals overlapping `[left, right)`.
+    pub fn overlapping(
+        &self,
+        left: usize,
+        right: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.0.overlapping(left, right).map(|i| (i.left, i.right))
+    }
+    /// Check if `[left, right)` is fully covered by intervals.
+    pub fn covers(&self, left: usize, right: usize) -> bool {
+        self.0.covers(left, right)
+    }
+    /// Uncovered parts of `[left, right)`, in ascending order.
+    pub fn gaps(&self, left: usize, right: usize) -> Vec<(usize, usize)> {
+        self.0.gaps(left, right)
+    }
+    /// Find the lowest uncovered range of `size` within `[left, right)`,
+    /// returning its left bound.
+    pub fn find_gap(&self, size: usize, left: usize, right: usize) -> Option<usize> {
+        self.0.find_gap(size, left, right)
+    }
+    /// Add `[left, right)`, merging it with overlapping and adjacent intervals.
+    pub fn insert(&mut self, left: usize, right: usize) {
+        self.0.insert(left, right, ());
+    }
+    /// Remove `[left, right)`, splitting intervals overlapping it. Return the
+    /// removed parts.
+    pub fn remove(&mut self, left: usize, right: usize) -> Vec<(usize, usize)> {
+        self.0
+            .remove(left, right)
+            .into_iter()
+            .map(|i| (i.left, i.right))
+            .collect()
+    }
+}
+
+impl AbstractState for IntervalSet {
+    fn matches(&self, other: &Self) -> bool {
+        self.0.matches(&other.0)
+    }
+    fn update(&mut self, other: &Self) {
+        self.0.update(&other.0)
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        // Bounds are the whole state of a set interval
+        let mut diffs: Vec<String> = self
+            .0
+            .diff(&other.0)
+            .into_iter()
+            .map(|p| match split_index(&p) {
+                Some((left, _)) => format!("[{}]", left),
+                None => p,
+            })
+            .collect();
+        diffs.dedup();
+        diffs
+    }
+    fn key(&self) -> u64 {
+        self.0.key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds<T>(map: &IntervalMap<T>) -> Vec<(usize, usize)> {
+        map.iter().map(|i| (i.left, i.right)).collect()
+    }
+
+    #[test]
+    fn insert_splits_and_merges() {
+        let mut map = IntervalMap::new();
+        map.insert(0, 100, 1u8);
+        map.insert(40, 60, 2);
+        assert_eq!(bounds(&map), vec![(0, 40), (40, 60), (60, 100)]);
+        assert_eq!(map.get(50).map(|i| i.value), Some(2));
+        // Equal adjacent values are merged back.
+        map.insert(40, 60, 1);
+        assert_eq!(bounds(&map), vec![(0, 100)]);
+        map.insert(100, 120, 1);
+        assert_eq!(bounds(&map), vec![(0, 120)]);
+        map.insert(10, 10, 3);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn remove_and_modify() {
+        let mut map = IntervalMap::new();
+        map.insert(0, 100, 1u8);
+        let removed = map.remove(20, 30);
+        let parts: Vec<_> = removed.iter().map(|i| (i.left, i.right, i.value)).collect();
+        assert_eq!(parts, vec![(20, 30, 1)]);
+        assert_eq!(bounds(&map), vec![(0, 20), (30, 100)]);
+        map.modify(10, 50, |v| *v = 2);
+        assert_eq!(bounds(&map), vec![(0, 10), (10, 20), (30, 50), (50, 100)]);
+        map.modify(0, 100, |v| *v = 3);
+        assert_eq!(bounds(&map), vec![(0, 20), (30, 100)]);
+        assert!(!map.contains(25));
+    }
+
+    #[test]
+    fn gaps_and_overlapping() {
+        let mut set = IntervalSet::new();
+        set.insert(10, 20);
+        set.insert(30, 40);
+        set.insert(20, 25);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![(10, 25), (30, 40)]);
+        assert_eq!(
+            set.overlapping(0, 31).collect::<Vec<_>>(),
+            vec![(10, 25), (30, 40)]
+        );
+        assert_eq!(set.overlapping(25, 30).count(), 0);
+        assert_eq!(set.gaps(0, 50), vec![(0, 10), (25, 30), (40, 50)]);
+        assert!(set.covers(12, 25));
+        assert!(!set.covers(12, 26));
+        assert_eq!(set.find_gap(6, 5, 50), Some(40));
+        assert_eq!(set.find_gap(5, 10, 50), Some(25));
+        assert_eq!(set.find_gap(20, 0, 50), None);
+    }
+
+    #[test]
+    fn state_paths() {
+        let mut model = IntervalMap::new();
+        model.insert(0, 10, 1u8);
+        model.insert(10, 20, 2);
+        let mut target = model.clone();
+        target.insert(10, 20, 3);
+        target.insert(30, 40, 1);
+        assert!(!target.matches(&model));
+        assert_eq!(model.diff(&target), vec!["[10].value", "[30]"]);
+        model.update(&target);
+        assert!(target.matches(&model));
+
+        let mut set = IntervalSet::new();
+        set.insert(0, 10);
+        let mut other = IntervalSet::new();
+        other.insert(0, 12);
+        assert_eq!(set.diff(&other), vec!["[0]"]);
+    }
+}
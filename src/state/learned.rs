@@ -0,0 +1,86 @@
+use super::AbstractState;
+use crate::CheckLevel;
+
+/// A value the model can't predict, e.g. a pid, an inode number or an mmap
+/// address chosen by the kernel.
+///
+/// `Unknown` until observed from the target with `learn` (or `update`), then
+/// checked like `T`. An unknown value matches anything, so keys leave it
+/// out, known or not. Commands refer to a
+/// learned value symbolically by its place in the model state, e.g. the
+/// index of a process, and resolve it with `get`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Learned<T> {
+    /// Not observed yet.
+    #[default]
+    Unknown,
+    /// Observed or predicted value.
+    Known(T),
+}
+
+impl<T> Learned<T> {
+    /// Get the value if it is known.
+    pub fn get(&self) -> Option<&T> {
+        match self {
+            Self::Known(v) => Some(v),
+            Self::Unknown => None,
+        }
+    }
+    /// Get the value mutably if it is known.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        match self {
+            Self::Known(v) => Some(v),
+            Self::Unknown => None,
+        }
+    }
+    /// Check if the value is known.
+    pub fn is_known(&self) -> bool {
+        matches!(self, Self::Known(_))
+    }
+    /// Set the value, e.g. when the model can predict it after all.
+    pub fn set(&mut self, value: T) {
+        *self = Self::Known(value);
+    }
+    /// Forget the value, e.g. when the object is released and the value may
+    /// be chosen again.
+    pub fn forget(&mut self) -> Option<T> {
+        match core::mem::take(self) {
+            Self::Known(v) => Some(v),
+            Self::Unknown => None,
+        }
+    }
+}
+
+impl<T> AbstractState for Learned<T>
+where
+    T: AbstractState + Clone,
+{
+    fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Known(a), Self::Known(b)) => a.matches(b),
+            _ => true,
+        }
+    }
+    fn update(&mut self, other: &Self) {
+        match (&mut *self, other) {
+            (Self::Known(a), Self::Known(b)) => a.update(b),
+            _ => *self = other.clone(),
+        }
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        match (self, other) {
+            (Self::Known(a), Self::Known(b)) => a.diff(b),
+            _ => Vec::new(),
+        }
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        self.get().and_then(|v| v.level(path))
+    }
+    fn learn(&mut self, other: &Self) {
+        match (&mut *self, other) {
+            (Self::Known(a), Self::Known(b)) => a.learn(b),
+            (Self::Unknown, Self::Known(b)) => *self = Self::Known(b.clone()),
+            _ => {}
+        }
+    }
+}
@@ -1,17 +1,199 @@
+mod collections;
 mod ignored;
 mod interval;
+mod interval_map;
+mod learned;
+mod page_table;
+mod renamed;
 mod value;
 
 pub use ignored::Ignored;
 pub use interval::Interval;
+pub use interval_map::{IntervalMap, IntervalSet};
+pub use learned::Learned;
+pub use page_table::{FrameMatch, PageMapping, PagePerms, PageTable};
+pub use renamed::{RefsFn, RenamedMap};
 pub use value::{Value, ValueList, ValueMap, ValueSet};
 
+use crate::CheckLevel;
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
+
 /// Generic Kernel State Type.
 pub trait AbstractState {
     /// Check if the current state matches the other state.
     fn matches(&self, other: &Self) -> bool;
     /// Update the current state with the other state.
     fn update(&mut self, other: &Self);
+    /// Paths of the parts of the current state that don't match the other state.
+    ///
+    /// Paths are relative to self, e.g. `files[3].size`. The empty path stands
+    /// for the whole state, which is the default when they don't match.
+    fn diff(&self, other: &Self) -> Vec<String> {
+        if self.matches(other) {
+            Vec::new()
+        } else {
+            vec![String::new()]
+        }
+    }
+    /// (optional) Check level of the part of the state at `path`, a path
+    /// returned by `diff`.
+    ///
+    /// `None` (the default) leaves the level to the checker. When parts of
+    /// different levels differ, the checker applies the most severe one.
+    fn level(&self, _path: &str) -> Option<CheckLevel> {
+        None
+    }
+    /// (optional) Take the parts of the other state the model can't predict,
+    /// e.g. `Learned` values not observed yet.
+    ///
+    /// Called by the checker with the target state before comparing them.
+    fn learn(&mut self, _other: &Self) {}
+    /// (optional) Hash of the parts of the state `matches` compares, e.g. to
+    /// tell states apart in coverage points.
+    ///
+    /// States that match must have the same key, so parts that match loosely
+    /// (unknown, ignored or compared with a tolerance) are left out. The
+    /// default `0` is the same for all states.
+    fn key(&self) -> u64 {
+        0
+    }
+}
+
+/// Join a path of a state and a path relative to it.
+///
+/// `join_path("files", "[3].size")` is `files[3].size` and
+/// `join_path("files", "size")` is `files.size`.
+pub fn join_path(prefix: &str, path: &str) -> String {
+    if prefix.is_empty() || path.is_empty() || path.starts_with('[') {
+        format!("{}{}", prefix, path)
+    } else {
+        format!("{}.{}", prefix, path)
+    }
+}
+
+/// 64-bit FNV-1a hash of bytes, stable across runs and platforms.
+///
+/// Also the default id of a derived `Command` (the hash of its type name), so
+/// changing it changes the ids of recorded traces.
+pub const fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Compute a fingerprint of a state from its `Debug` representation.
+///
+/// The hash (`fnv1a`) is stable across runs and platforms as long as the
+/// `Debug` output is, which is not the case of e.g. a `HashMap`.
+pub fn fingerprint<S>(state: &S) -> u64
+where
+    S: Debug + ?Sized,
+{
+    fnv1a(format!("{:?}", state).as_bytes())
+}
+
+/// `Hasher` computing `fnv1a` of the written bytes.
+struct KeyHasher(u64);
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Key of a hashable value, see `AbstractState::key`.
+///
+/// Stable across runs, unlike the keys of `std`'s `RandomState`.
+pub fn key_of<T>(value: &T) -> u64
+where
+    T: Hash + ?Sized,
+{
+    let mut hasher = KeyHasher(fnv1a(&[]));
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Key of a sequence of keys, e.g. of the fields of a struct.
+pub fn ordered_key(keys: impl IntoIterator<Item = u64>) -> u64 {
+    let mut hasher = KeyHasher(fnv1a(&[]));
+    for key in keys {
+        hasher.write_u64(key);
+    }
+    hasher.finish()
+}
+
+/// Key of a set of keys, independent of their order.
+pub fn unordered_key(keys: impl IntoIterator<Item = u64>) -> u64 {
+    keys.into_iter()
+        .fold(0, |acc, key| acc.wrapping_add(key_of(&key)))
+}
+
+/// Strip the path of a part of a state from a path, the inverse of `join_path`.
+///
+/// `strip_path("files[3].size", "files")` is `Some("[3].size")` and
+/// `strip_path("files.size", "files")` is `Some("size")`.
+pub fn strip_path<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if prefix.is_empty() || rest.is_empty() || rest.starts_with('[') {
+        Some(rest)
+    } else {
+        rest.strip_prefix('.')
+    }
+}
+
+/// Split the leading `[index]` of a path, e.g. `[3].size` into `3` and `size`.
+pub(crate) fn split_index(path: &str) -> Option<(&str, &str)> {
+    let end = index_end(path)?;
+    let rest = &path[end + 1..];
+    Some((&path[1..end], rest.strip_prefix('.').unwrap_or(rest)))
+}
+
+/// Position of the `]` closing the `[` a path starts with.
+///
+/// Indices are `Debug` representations of keys, so nested brackets are
+/// matched and brackets in quoted strings and chars are skipped, e.g. the
+/// index of `[("a]", [1])].size` is `("a]", [1])`.
+pub(crate) fn index_end(path: &str) -> Option<usize> {
+    if !path.starts_with('[') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in path.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
 }
 
 /// Implements AbstractState for some basic types
@@ -22,12 +204,32 @@ macro_rules! impl_AbstractState {
                 self == other
             }
             fn update(&mut self, other: &Self) { *self = other.clone(); }
+            fn key(&self) -> u64 {
+                key_of(self)
+            }
         })*
     }
 }
 
 impl_AbstractState!(for u8, i8, u16, i16, u32, i32, u64, i64, u128,
-    i128, usize, isize, f32, f64, bool, char, String, &str);
+    i128, usize, isize, bool, char, String, &str, ());
+
+/// Implements AbstractState for floats, `0.0` and `-0.0` have the same key
+macro_rules! impl_AbstractState_float {
+    (for $($t:ty),+) => {
+        $(impl AbstractState for $t {
+            fn matches(&self, other: &Self) -> bool {
+                self == other
+            }
+            fn update(&mut self, other: &Self) { *self = *other; }
+            fn key(&self) -> u64 {
+                key_of(&(self + 0.0).to_bits())
+            }
+        })*
+    }
+}
+
+impl_AbstractState_float!(for f32, f64);
 
 impl<T> AbstractState for Option<T>
 where
@@ -52,4 +254,96 @@ where
             None => *self = None,
         }
     }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        match (self, other) {
+            (Some(a), Some(b)) => a.diff(b),
+            (None, None) => Vec::new(),
+            _ => vec![String::new()],
+        }
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        self.as_ref().and_then(|v| v.level(path))
+    }
+    fn learn(&mut self, other: &Self) {
+        if let (Some(a), Some(b)) = (self, other) {
+            a.learn(b);
+        }
+    }
+    fn key(&self) -> u64 {
+        match self {
+            Some(v) => ordered_key([1, v.key()]),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+        assert_eq!(fingerprint("a"), fnv1a(br#""a""#));
+    }
+
+    #[test]
+    fn keys_of_matching_states() {
+        use std::collections::HashSet;
+        assert_eq!(0.0f64.key(), (-0.0f64).key());
+        assert_ne!(1.0f64.key(), 2.0f64.key());
+        let a: HashSet<u32> = (0..50).collect();
+        let b: HashSet<u32> = (0..50).rev().collect();
+        assert_eq!(a.key(), b.key());
+        let known = (Learned::Known(1u32), Ignored(1u8), Some("a"));
+        let unknown = (Learned::Unknown, Ignored(2u8), Some("a"));
+        assert!(known.matches(&unknown));
+        assert_eq!(known.key(), unknown.key());
+        assert_ne!(
+            known.key(),
+            (Learned::<u32>::Unknown, Ignored(2u8), None::<&str>).key()
+        );
+        let set = ValueSet(vec![Value(1u8), Value(2)]);
+        assert_eq!(set.key(), ValueSet(vec![Value(2u8), Value(1)]).key());
+        assert_eq!(ordered_key([]), ordered_key(Vec::new()));
+        assert_ne!(ordered_key([1, 2]), ordered_key([2, 1]));
+    }
+
+    #[test]
+    fn paths_join_and_strip() {
+        assert_eq!(join_path("files", "[3].size"), "files[3].size");
+        assert_eq!(join_path("files", "size"), "files.size");
+        assert_eq!(join_path("", "size"), "size");
+        assert_eq!(strip_path("files[3].size", "files"), Some("[3].size"));
+        assert_eq!(strip_path("files.size", "files"), Some("size"));
+        assert_eq!(strip_path("filesize", "files"), None);
+    }
+
+    #[test]
+    fn split_index_matches_brackets() {
+        assert_eq!(split_index("[3].size"), Some(("3", "size")));
+        assert_eq!(split_index("[[1, 2]][0]"), Some(("[1, 2]", "[0]")));
+        assert_eq!(split_index(r#"["a]b"].x"#), Some((r#""a]b""#, "x")));
+        assert_eq!(split_index(r#"["a\"]"]"#), Some((r#""a\"]""#, "")));
+        assert_eq!(split_index("[(']', [2])].y"), Some(("(']', [2])", "y")));
+        assert_eq!(split_index("size"), None);
+        assert_eq!(split_index("[3"), None);
+    }
+
+    #[test]
+    fn level_of_debug_keys_with_brackets() {
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("a]".to_string(), Some(1u8));
+        map.insert("b".to_string(), None);
+        let other = map.clone();
+        assert!(map.matches(&other));
+        // Paths of keys round-trip through `level`.
+        let mut changed = other.clone();
+        changed.insert("a]".to_string(), Some(2));
+        assert_eq!(map.diff(&changed), vec![r#"["a]"]"#]);
+        assert_eq!(map.level(r#"["a]"]"#), None);
+        assert_eq!(split_index(r#"["a]"]"#).map(|(k, _)| k), Some(r#""a]""#));
+    }
 }
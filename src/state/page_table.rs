@@ -0,0 +1,315 @@
+use super::{join_path, key_of, AbstractState};
+use std::collections::BTreeMap;
+
+/// Access permissions of a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PagePerms {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    /// Accessible from user mode.
+    pub user: bool,
+}
+
+impl PagePerms {
+    pub fn new(read: bool, write: bool, exec: bool, user: bool) -> Self {
+        Self {
+            read,
+            write,
+            exec,
+            user,
+        }
+    }
+}
+
+impl AbstractState for PagePerms {
+    fn matches(&self, other: &Self) -> bool {
+        self == other
+    }
+    fn update(&mut self, other: &Self) {
+        *self = *other;
+    }
+    fn key(&self) -> u64 {
+        key_of(self)
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        [
+            ("read", self.read != other.read),
+            ("write", self.write != other.write),
+            ("exec", self.exec != other.exec),
+            ("user", self.user != other.user),
+        ]
+        .iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+}
+
+/// A mapping of a virtual page to a physical frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageMapping {
+    /// Physical address of the frame.
+    pub frame: usize,
+    /// Page size in bytes, e.g. 4K, 2M or 1G.
+    pub size: usize,
+    pub perms: PagePerms,
+}
+
+impl PageMapping {
+    pub fn new(frame: usize, size: usize, perms: PagePerms) -> Self {
+        Self { frame, size, perms }
+    }
+}
+
+/// How physical frames of two page tables are compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameMatch {
+    /// Frames must be equal.
+    #[default]
+    Exact,
+    /// Frames must be equal up to a consistent renaming, i.e. pages share a
+    /// frame in one table iff they share a frame in the other.
+    Equivalent,
+}
+
+/// Virtual-to-physical page mappings with permissions.
+///
+/// Mappings are identified by their virtual address in state paths, e.g.
+/// `[4096].perms.write`. Frames are compared up to renaming if either table
+/// is set to `FrameMatch::Equivalent` with `frames`, so the comparison is the
+/// same whichever table is the model.
+#[derive(Debug, Clone, Default)]
+pub struct PageTable {
+    mappings: BTreeMap<usize, PageMapping>,
+    frame_match: FrameMatch,
+}
+
+impl PageTable {
+    /// Create an empty page table comparing frames exactly.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set how frames are compared.
+    pub fn frames(mut self, frame_match: FrameMatch) -> Self {
+        self.frame_match = frame_match;
+        self
+    }
+    /// Number of mapped pages.
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+    /// Check if no page is mapped.
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+    /// Iterate over virtual addresses and mappings of pages in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &PageMapping)> {
+        self.mappings.iter().map(|(&vaddr, m)| (vaddr, m))
+    }
+    /// Map the page at `vaddr`, replacing pages overlapping it. Return the
+    /// replaced mappings.
+    pub fn map(&mut self, vaddr: usize, mapping: PageMapping) -> Vec<(usize, PageMapping)> {
+        let replaced = self.unmap_range(vaddr, vaddr.saturating_add(mapping.size));
+        self.mappings.insert(vaddr, mapping);
+        replaced
+    }
+    /// Unmap the page at `vaddr`.
+    pub fn unmap(&mut self, vaddr: usize) -> Option<PageMapping> {
+        self.mappings.remove(&vaddr)
+    }
+    /// Unmap all pages overlapping `[left, right)`. Return the removed mappings.
+    pub fn unmap_range(&mut self, left: usize, right: usize) -> Vec<(usize, PageMapping)> {
+        let vaddrs: Vec<usize> = self.overlapping(left, right).map(|(v, _)| v).collect();
+        vaddrs
+            .into_iter()
+            .filter_map(|v| self.mappings.remove(&v).map(|m| (v, m)))
+            .collect()
+    }
+    /// Change the permissions of the page at `vaddr`. Return `false` if it is
+    /// not mapped.
+    pub fn protect(&mut self, vaddr: usize, perms: PagePerms) -> bool {
+        match self.mappings.get_mut(&vaddr) {
+            Some(m) => {
+                m.perms = perms;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Get the page containing `addr`, with its virtual address.
+    pub fn get(&self, addr: usize) -> Option<(usize, &PageMapping)> {
+        self.mappings
+            .range(..=addr)
+            .next_back()
+            .filter(|(&vaddr, m)| addr - vaddr < m.size)
+            .map(|(&vaddr, m)| (vaddr, m))
+    }
+    /// Translate a virtual address to a physical address.
+    pub fn translate(&self, addr: usize) -> Option<usize> {
+        self.get(addr)
+            .and_then(|(vaddr, m)| m.frame.checked_add(addr - vaddr))
+    }
+    /// Iterate over pages overlapping `[left, right)`.
+    pub fn overlapping(
+        &self,
+        left: usize,
+        right: usize,
+    ) -> impl Iterator<Item = (usize, &PageMapping)> {
+        let start = self.get(left).map_or(left, |(vaddr, _)| vaddr);
+        self.mappings
+            .range(start..right.max(start))
+            .map(|(&vaddr, m)| (vaddr, m))
+    }
+
+    /// Virtual addresses of pages mapped in both tables whose frames don't
+    /// match.
+    fn frame_diff(&self, other: &Self) -> Vec<usize> {
+        let mut forward = BTreeMap::new();
+        let mut backward = BTreeMap::new();
+        let frame_match = if other.frame_match == FrameMatch::Equivalent {
+            FrameMatch::Equivalent
+        } else {
+            self.frame_match
+        };
+        let mut diffs = Vec::new();
+        for (vaddr, m) in &self.mappings {
+            let Some(om) = other.mappings.get(vaddr) else {
+                continue;
+            };
+            let consistent = match frame_match {
+                FrameMatch::Exact => m.frame == om.frame,
+                FrameMatch::Equivalent => {
+                    *forward.entry(m.frame).or_insert(om.frame) == om.frame
+                        && *backward.entry(om.frame).or_insert(m.frame) == m.frame
+                }
+            };
+            if !consistent {
+                diffs.push(*vaddr);
+            }
+        }
+        diffs
+    }
+}
+
+impl AbstractState for PageTable {
+    fn matches(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|((va, a), (vb, b))| va == vb && a.size == b.size && a.perms == b.perms)
+            && self.frame_diff(other).is_empty()
+    }
+    /// Take the mappings of the other table, keep the frame comparison.
+    fn update(&mut self, other: &Self) {
+        self.mappings.clone_from(&other.mappings);
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let frame_diffs = self.frame_diff(other);
+        let mut diffs = Vec::new();
+        for (vaddr, m) in &self.mappings {
+            let prefix = format!("[{}]", vaddr);
+            let Some(om) = other.mappings.get(vaddr) else {
+                diffs.push(prefix);
+                continue;
+            };
+            if frame_diffs.contains(vaddr) {
+                diffs.push(join_path(&prefix, "frame"));
+            }
+            if m.size != om.size {
+                diffs.push(join_path(&prefix, "size"));
+            }
+            let perms = join_path(&prefix, "perms");
+            diffs.extend(m.perms.diff(&om.perms).iter().map(|p| join_path(&perms, p)));
+        }
+        for vaddr in other.mappings.keys() {
+            if !self.mappings.contains_key(vaddr) {
+                diffs.push(format!("[{}]", vaddr));
+            }
+        }
+        diffs
+    }
+    /// Frames may be renamed, so the key leaves them out.
+    fn key(&self) -> u64 {
+        let pages: Vec<_> = self
+            .iter()
+            .map(|(vaddr, m)| (vaddr, m.size, m.perms))
+            .collect();
+        key_of(&pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RW: PagePerms = PagePerms {
+        read: true,
+        write: true,
+        exec: false,
+        user: true,
+    };
+
+    fn table(pages: &[(usize, usize)]) -> PageTable {
+        let mut table = PageTable::new();
+        for &(vaddr, frame) in pages {
+            table.map(vaddr, PageMapping::new(frame, 0x1000, RW));
+        }
+        table
+    }
+
+    #[test]
+    fn equivalent_frames_match_both_ways() {
+        let model = table(&[(0x1000, 0x10000), (0x2000, 0x10000), (0x3000, 0x20000)])
+            .frames(FrameMatch::Equivalent);
+        let target = table(&[(0x1000, 0x50000), (0x2000, 0x50000), (0x3000, 0x60000)]);
+        assert!(target.matches(&model));
+        assert!(model.matches(&target));
+        assert!(model.diff(&target).is_empty());
+        assert!(!target.matches(
+            &table(&[(0x1000, 0x10000), (0x2000, 0x10000), (0x3000, 0x20000)])
+                .frames(FrameMatch::Exact)
+        ));
+    }
+
+    #[test]
+    fn sharing_must_be_preserved() {
+        let model = table(&[(0x1000, 0x10000), (0x2000, 0x10000)]).frames(FrameMatch::Equivalent);
+        let target = table(&[(0x1000, 0x50000), (0x2000, 0x60000)]);
+        assert!(!target.matches(&model));
+        assert_eq!(model.diff(&target), vec!["[8192].frame"]);
+    }
+
+    #[test]
+    fn exact_frames() {
+        let model = table(&[(0x1000, 0x10000)]);
+        let target = table(&[(0x1000, 0x50000)]);
+        assert!(!target.matches(&model));
+        assert_eq!(model.diff(&target), vec!["[4096].frame"]);
+    }
+
+    #[test]
+    fn map_lookup_and_unmap() {
+        let mut t = table(&[(0x1000, 0x10000), (0x2000, 0x20000)]);
+        assert_eq!(t.translate(0x1010), Some(0x10010));
+        assert_eq!(t.translate(0x3000), None);
+        let huge = PageMapping::new(0x200000, 0x200000, RW);
+        assert_eq!(t.map(0, huge).len(), 2);
+        assert_eq!(t.get(0x1fffff).map(|(v, _)| v), Some(0));
+        assert!(t.protect(0, PagePerms::default()));
+        assert_eq!(t.unmap_range(0x100000, 0x100001).len(), 1);
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn top_of_address_space() {
+        let top = usize::MAX - 0xfff;
+        let mut t = PageTable::new();
+        t.map(top, PageMapping::new(0x1000, 0x1000, RW));
+        assert_eq!(t.get(usize::MAX).map(|(v, _)| v), Some(top));
+        assert_eq!(t.translate(usize::MAX), Some(0x1fff));
+        assert_eq!(t.map(top, PageMapping::new(0x2000, 0x1000, RW)).len(), 1);
+        assert_eq!(t.len(), 1);
+    }
+}
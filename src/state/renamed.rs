@@ -0,0 +1,470 @@
+use super::{join_path, key_of, split_index, value::max_matching, AbstractState};
+use crate::CheckLevel;
+use core::fmt::Debug;
+use core::ops::{Deref, DerefMut};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Visit each key a value refers to, e.g. `|p, visit| visit(&mut p.ppid)`.
+pub type RefsFn<K, V> = fn(&mut V, &mut dyn FnMut(&mut K));
+
+/// Map keyed by identifiers that may differ between model and target, e.g.
+/// pids, fds or inode numbers.
+///
+/// Two maps match if there is a bijection between their keys such that paired
+/// values match. The bijection found with `learn` is kept in the model state:
+/// a pair stays fixed until the key is removed from the model, and new keys
+/// are paired with new keys of the target by a one-to-one matching of values.
+/// Commands translate keys with `renamed` and `original`.
+///
+/// Values may refer to keys of the map, e.g. the parent pid of a process.
+/// With `refs`, these references are renamed as well when values are
+/// compared, updated and learned.
+///
+/// Paths of differing values use keys of self, e.g. `[3].cwd`.
+#[derive(Debug, Clone)]
+pub struct RenamedMap<K, V>
+where
+    K: Ord,
+{
+    map: BTreeMap<K, V>,
+    /// Keys of self paired with keys of the other state.
+    renaming: BTreeMap<K, K>,
+    /// Keys referred to by values.
+    refs: Option<RefsFn<K, V>>,
+}
+
+impl<K, V> Default for RenamedMap<K, V>
+where
+    K: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keys of two maps paired by their renamings and by matching values.
+struct Pairing<'a, K> {
+    /// Pairs fixed by a renaming.
+    fixed: Vec<(&'a K, &'a K)>,
+    /// Pairs of keys without renaming, found by matching values.
+    found: Vec<(&'a K, &'a K)>,
+    /// Unpaired keys of self.
+    own: Vec<&'a K>,
+    /// Unpaired keys of other.
+    others: Vec<&'a K>,
+    /// All pairs, from keys of self to keys of other.
+    renaming: BTreeMap<&'a K, &'a K>,
+}
+
+/// A value with the keys it refers to renamed, keys without a renaming are
+/// kept.
+fn rename_refs<'v, K, V>(
+    refs: Option<RefsFn<K, V>>,
+    value: &'v V,
+    renaming: &BTreeMap<&K, &K>,
+) -> Cow<'v, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    match refs {
+        Some(refs) if !renaming.is_empty() => {
+            let mut value = value.clone();
+            refs(&mut value, &mut |key| {
+                if let Some(&renamed) = renaming.get(key) {
+                    *key = renamed.clone();
+                }
+            });
+            Cow::Owned(value)
+        }
+        _ => Cow::Borrowed(value),
+    }
+}
+
+impl<K, V> RenamedMap<K, V>
+where
+    K: Ord,
+{
+    pub fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+            renaming: BTreeMap::new(),
+            refs: None,
+        }
+    }
+    /// Set how to find the keys values refer to, so they are renamed too.
+    pub fn refs(mut self, refs: RefsFn<K, V>) -> Self {
+        self.refs = Some(refs);
+        self
+    }
+    /// Key of the target paired with a key of the model.
+    pub fn renamed(&self, key: &K) -> Option<&K> {
+        self.renaming.get(key)
+    }
+    /// Key of the model paired with a key of the target.
+    pub fn original(&self, key: &K) -> Option<&K> {
+        self.renaming
+            .iter()
+            .find(|(_, k)| *k == key)
+            .map(|(k, _)| k)
+    }
+    /// Iterate over pairs of model and target keys.
+    pub fn renaming(&self) -> impl Iterator<Item = (&K, &K)> {
+        self.renaming.iter()
+    }
+}
+
+impl<K, V> RenamedMap<K, V>
+where
+    K: Ord + Clone,
+    V: AbstractState + Clone,
+{
+    /// Keys referred to by values of either map.
+    fn refs_of(&self, other: &Self) -> Option<RefsFn<K, V>> {
+        self.refs.or(other.refs)
+    }
+    /// Pair keys of self and other, keeping pairs of both renamings.
+    ///
+    /// Values referring to keys are compared once the keys are paired, so
+    /// matching is repeated until the pairs found are stable.
+    fn pairing<'a>(&'a self, other: &'a Self) -> Pairing<'a, K> {
+        let mut fixed = BTreeMap::new();
+        for (k, ok) in &self.renaming {
+            if self.map.contains_key(k) {
+                fixed.insert(k, ok);
+            }
+        }
+        for (ok, k) in &other.renaming {
+            if other.map.contains_key(ok) {
+                fixed.entry(k).or_insert(ok);
+            }
+        }
+        // Keys paired with a missing key are left unpaired, but not free
+        let paired_others: BTreeSet<&K> = fixed.values().copied().collect();
+        let mut own = Vec::new();
+        let mut others = Vec::new();
+        let mut pairs = Vec::new();
+        for (k, ok) in fixed {
+            match (self.map.contains_key(k), other.map.contains_key(ok)) {
+                (true, true) => pairs.push((k, ok)),
+                (true, false) => own.push(k),
+                (false, true) => others.push(ok),
+                (false, false) => {}
+            }
+        }
+        let paired_own: BTreeSet<&K> = pairs.iter().map(|(k, _)| *k).collect();
+        let free_own: Vec<&K> = self
+            .map
+            .keys()
+            .filter(|k| !paired_own.contains(k) && !own.contains(k))
+            .collect();
+        let free_others: Vec<&K> = other
+            .map
+            .keys()
+            .filter(|k| !paired_others.contains(k))
+            .collect();
+        let refs = self.refs_of(other);
+        let mut found: Vec<(&K, &K)> = Vec::new();
+        for _ in 0..=free_own.len() {
+            let renaming: BTreeMap<&K, &K> = pairs.iter().chain(&found).copied().collect();
+            let values: Vec<Cow<V>> = free_own
+                .iter()
+                .map(|k| rename_refs(refs, &self.map[*k], &renaming))
+                .collect();
+            let owner = max_matching(free_own.len(), free_others.len(), |i, j| {
+                values[i].matches(&other.map[free_others[j]])
+            });
+            let next: Vec<(&K, &K)> = owner
+                .iter()
+                .enumerate()
+                .filter_map(|(j, i)| i.map(|i| (free_own[i], free_others[j])))
+                .collect();
+            let stable = refs.is_none() || next == found;
+            found = next;
+            if stable {
+                break;
+            }
+        }
+        let matched_own: BTreeSet<&K> = found.iter().map(|(k, _)| *k).collect();
+        let matched_others: BTreeSet<&K> = found.iter().map(|(_, ok)| *ok).collect();
+        others.extend(
+            free_others
+                .into_iter()
+                .filter(|ok| !matched_others.contains(ok)),
+        );
+        own.extend(free_own.into_iter().filter(|k| !matched_own.contains(k)));
+        let renaming = pairs.iter().chain(&found).copied().collect();
+        Pairing {
+            fixed: pairs,
+            found,
+            own,
+            others,
+            renaming,
+        }
+    }
+    /// Value of self at `k` with references renamed to keys of other.
+    fn value_for<'a>(&'a self, k: &K, other: &Self, pairing: &Pairing<K>) -> Cow<'a, V> {
+        rename_refs(self.refs_of(other), &self.map[k], &pairing.renaming)
+    }
+}
+
+impl<K, V> AbstractState for RenamedMap<K, V>
+where
+    K: Ord + Clone + Debug,
+    V: AbstractState + Clone,
+{
+    fn matches(&self, other: &Self) -> bool {
+        let pairing = self.pairing(other);
+        pairing.own.is_empty()
+            && pairing.others.is_empty()
+            && pairing
+                .fixed
+                .iter()
+                .all(|(k, ok)| self.value_for(k, other, &pairing).matches(&other.map[*ok]))
+    }
+    /// Take the entries of the other state, keeping the keys of self.
+    ///
+    /// Paired values are updated, unpaired keys of both states are paired in
+    /// order. Remaining keys of self are removed, remaining keys of other are
+    /// taken as they are unless self already uses them.
+    fn update(&mut self, other: &Self) {
+        let pairing = self.pairing(other);
+        let mut pairs: Vec<(K, K)> = pairing
+            .fixed
+            .iter()
+            .chain(&pairing.found)
+            .map(|(k, ok)| ((*k).clone(), (*ok).clone()))
+            .collect();
+        let mut own = pairing.own.into_iter().cloned();
+        let mut unpaired = Vec::new();
+        for ok in pairing.others.into_iter().cloned() {
+            match own.next() {
+                Some(k) => pairs.push((k, ok)),
+                None => unpaired.push(ok),
+            }
+        }
+        let removed: Vec<K> = own.collect();
+        for k in removed {
+            self.map.remove(&k);
+        }
+        // Values of other refer to keys of other
+        let refs = self.refs_of(other);
+        let inverse: BTreeMap<&K, &K> = pairs.iter().map(|(k, ok)| (ok, k)).collect();
+        for (k, ok) in &pairs {
+            let ov = rename_refs(refs, &other.map[ok], &inverse);
+            match self.map.get_mut(k) {
+                Some(v) => v.update(&ov),
+                None => {
+                    self.map.insert(k.clone(), ov.into_owned());
+                }
+            }
+            self.renaming.insert(k.clone(), ok.clone());
+        }
+        for ok in unpaired {
+            if !self.map.contains_key(&ok) {
+                let ov = rename_refs(refs, &other.map[&ok], &inverse).into_owned();
+                self.map.insert(ok.clone(), ov);
+                self.renaming.insert(ok.clone(), ok);
+            }
+        }
+        let map = &self.map;
+        self.renaming.retain(|k, _| map.contains_key(k));
+    }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let pairing = self.pairing(other);
+        let mut diffs = Vec::new();
+        for (k, ok) in &pairing.fixed {
+            let prefix = format!("[{:?}]", k);
+            diffs.extend(
+                self.value_for(k, other, &pairing)
+                    .diff(&other.map[*ok])
+                    .iter()
+                    .map(|p| join_path(&prefix, p)),
+            );
+        }
+        for k in pairing.own.iter().chain(&pairing.others) {
+            diffs.push(format!("[{:?}]", k));
+        }
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (key, rest) = split_index(path)?;
+        self.map
+            .iter()
+            .find(|(k, _)| format!("{:?}", k) == key)?
+            .1
+            .level(rest)
+    }
+    /// Forget pairs of removed keys, pair new keys and learn paired values.
+    fn learn(&mut self, other: &Self) {
+        let map = &self.map;
+        self.renaming.retain(|k, _| map.contains_key(k));
+        let pairing = self.pairing(other);
+        let pairs: Vec<(K, K)> = pairing
+            .fixed
+            .iter()
+            .chain(&pairing.found)
+            .map(|(k, ok)| ((*k).clone(), (*ok).clone()))
+            .collect();
+        let refs = self.refs_of(other);
+        let inverse: BTreeMap<&K, &K> = pairs.iter().map(|(k, ok)| (ok, k)).collect();
+        for (k, ok) in &pairs {
+            if let Some(v) = self.map.get_mut(k) {
+                v.learn(&rename_refs(refs, &other.map[ok], &inverse));
+            }
+            self.renaming.insert(k.clone(), ok.clone());
+        }
+    }
+    /// Keys may be renamed, and so may references in values, so only the
+    /// number of entries is hashed.
+    fn key(&self) -> u64 {
+        key_of(&self.map.len())
+    }
+}
+
+impl<K, V> Deref for RenamedMap<K, V>
+where
+    K: Ord,
+{
+    type Target = BTreeMap<K, V>;
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+impl<K, V> DerefMut for RenamedMap<K, V>
+where
+    K: Ord,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Value;
+
+    fn map(entries: &[(u32, &str)]) -> RenamedMap<u32, String> {
+        let mut map = RenamedMap::new();
+        for (k, v) in entries {
+            map.insert(*k, v.to_string());
+        }
+        map
+    }
+
+    #[test]
+    fn matches_up_to_renaming() {
+        let model = map(&[(1, "init"), (2, "sh")]);
+        let target = map(&[(7, "init"), (100, "sh")]);
+        assert!(model.matches(&target));
+        assert!(target.matches(&model));
+        assert!(!model.matches(&map(&[(7, "init"), (100, "ls")])));
+    }
+
+    #[test]
+    fn renaming_persists_across_rounds() {
+        let mut model = map(&[(1, "init"), (2, "sh")]);
+        model.learn(&map(&[(7, "init"), (100, "sh")]));
+        assert_eq!(model.renamed(&2), Some(&100));
+        assert_eq!(model.original(&7), Some(&1));
+        // Values swapped between the paired keys no longer match.
+        let swapped = map(&[(7, "sh"), (100, "init")]);
+        assert!(!swapped.matches(&model));
+        assert_eq!(model.diff(&swapped), vec!["[1]", "[2]"]);
+    }
+
+    #[test]
+    fn removed_keys_are_unpaired() {
+        let mut model = map(&[(1, "init"), (2, "sh")]);
+        model.learn(&map(&[(7, "init"), (100, "sh")]));
+        model.remove(&2);
+        model.insert(3, "ls".to_string());
+        // The target reuses id 100 for the new entry.
+        let target = map(&[(7, "init"), (100, "ls")]);
+        model.learn(&target);
+        assert!(target.matches(&model));
+        assert_eq!(model.renamed(&3), Some(&100));
+        assert_eq!(model.renamed(&2), None);
+        // An entry missing from the target is reported with the model key.
+        assert_eq!(model.diff(&map(&[(7, "init")])), vec!["[3]"]);
+    }
+
+    #[test]
+    fn update_keeps_model_keys() {
+        let mut model = map(&[(1, "init"), (2, "sh")]);
+        model.learn(&map(&[(7, "init"), (100, "sh")]));
+        let target = map(&[(7, "init"), (100, "vi"), (5, "ls")]);
+        model.update(&target);
+        assert!(target.matches(&model));
+        assert_eq!(model.keys().copied().collect::<Vec<_>>(), vec![1, 2, 5]);
+        assert_eq!(model[&2], "vi");
+        assert_eq!(model.renamed(&1), Some(&7));
+        assert_eq!(model.renamed(&2), Some(&100));
+        assert_eq!(model.renamed(&5), Some(&5));
+    }
+
+    #[test]
+    fn update_pairs_unmatched_keys() {
+        let mut model = map(&[(1, "init"), (2, "sh")]);
+        model.learn(&map(&[(7, "init"), (100, "sh")]));
+        // Entry 100 was replaced by 200 in the target.
+        let target = map(&[(7, "init"), (200, "ls")]);
+        model.update(&target);
+        assert_eq!(model.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(model.renamed(&2), Some(&200));
+        assert!(target.matches(&model));
+        // Extra model entries are removed.
+        model.update(&map(&[(7, "init")]));
+        assert_eq!(model.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(model.renaming().count(), 1);
+    }
+
+    /// Name and parent pid of a process.
+    type Proc = (String, Value<u32>);
+
+    /// Processes by pid.
+    fn procs(entries: &[(u32, &str, u32)]) -> RenamedMap<u32, Proc> {
+        let mut map = RenamedMap::new().refs(|proc: &mut Proc, visit| visit(&mut proc.1 .0));
+        for (pid, name, ppid) in entries {
+            map.insert(*pid, (name.to_string(), Value(*ppid)));
+        }
+        map
+    }
+
+    #[test]
+    fn references_in_values_are_renamed() {
+        let mut model = procs(&[(1, "init", 0), (2, "sh", 1), (3, "sh", 2)]);
+        let target = procs(&[(7, "init", 0), (100, "sh", 7), (101, "sh", 100)]);
+        assert!(target.matches(&model));
+        assert!(model.matches(&target));
+        model.learn(&target);
+        assert_eq!(model.renamed(&3), Some(&101));
+        // The parent of 101 changed to init.
+        let reparented = procs(&[(7, "init", 0), (100, "sh", 7), (101, "sh", 7)]);
+        assert!(!reparented.matches(&model));
+        assert_eq!(model.diff(&reparented), vec!["[3].1"]);
+        // A child of 100 is added on the target.
+        let forked = procs(&[
+            (7, "init", 0),
+            (100, "sh", 7),
+            (101, "sh", 100),
+            (102, "ls", 100),
+        ]);
+        model.update(&forked);
+        assert_eq!(model[&102].1 .0, 2);
+        assert!(forked.matches(&model));
+    }
+
+    #[test]
+    fn references_without_refs_are_raw() {
+        let mut model = RenamedMap::new();
+        model.insert(1, ("init".to_string(), Value(0)));
+        model.insert(2, ("sh".to_string(), Value(1)));
+        let mut target = RenamedMap::new();
+        target.insert(7, ("init".to_string(), Value(0)));
+        target.insert(100, ("sh".to_string(), Value(7)));
+        assert!(!target.matches(&model));
+    }
+}
@@ -1,14 +1,18 @@
-use super::AbstractState;
+use super::{join_path, key_of, ordered_key, split_index, unordered_key, AbstractState};
+use crate::CheckLevel;
+use core::hash::Hash;
 use core::ops::{Deref, DerefMut};
 use std::collections::BTreeMap;
 
 /// Type that is checked value-by-value.
+///
+/// Its key hashes the value, hence the `Hash` bound.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Value<T>(pub T);
 
 impl<T> AbstractState for Value<T>
 where
-    T: Eq + Clone,
+    T: Eq + Hash + Clone,
 {
     fn matches(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -16,6 +20,9 @@ where
     fn update(&mut self, other: &Self) {
         self.0 = other.0.clone();
     }
+    fn key(&self) -> u64 {
+        key_of(&self.0)
+    }
 }
 
 impl<T> Deref for Value<T> {
@@ -47,6 +54,32 @@ where
     fn update(&mut self, other: &Self) {
         self.0 = other.0.clone();
     }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        if self.0.len() != other.0.len() {
+            return vec![String::new()];
+        }
+        let mut diffs = Vec::new();
+        for (i, (a, b)) in self.0.iter().zip(other.0.iter()).enumerate() {
+            let prefix = format!("[{}]", i);
+            diffs.extend(a.diff(b).iter().map(|p| join_path(&prefix, p)));
+        }
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (index, rest) = split_index(path)?;
+        self.0.get(index.parse::<usize>().ok()?)?.level(rest)
+    }
+    fn learn(&mut self, other: &Self) {
+        if self.0.len() == other.0.len() {
+            self.0
+                .iter_mut()
+                .zip(other.0.iter())
+                .for_each(|(a, b)| a.learn(b));
+        }
+    }
+    fn key(&self) -> u64 {
+        ordered_key(self.0.iter().map(T::key))
+    }
 }
 
 impl<T> Deref for ValueList<T> {
@@ -61,11 +94,89 @@ impl<T> DerefMut for ValueList<T> {
     }
 }
 
-/// Unordered set of values that are checked value-by-value.
+/// Find a maximum matching of a bipartite graph with `left` and `right`
+/// vertices, by augmenting paths.
+///
+/// Return for each right vertex the index of its matched left vertex.
+pub(super) fn max_matching<F>(left: usize, right: usize, edge: F) -> Vec<Option<usize>>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    /// Try to match left vertex `i`, re-matching right vertices along an
+    /// augmenting path.
+    fn augment<F>(edge: &F, i: usize, visited: &mut [bool], owner: &mut [Option<usize>]) -> bool
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        for j in 0..owner.len() {
+            if visited[j] || !edge(i, j) {
+                continue;
+            }
+            visited[j] = true;
+            let free = match owner[j] {
+                Some(k) => augment(edge, k, visited, owner),
+                None => true,
+            };
+            if free {
+                owner[j] = Some(i);
+                return true;
+            }
+        }
+        false
+    }
+    let mut owner = vec![None; right];
+    for i in 0..left {
+        let mut visited = vec![false; right];
+        augment(&edge, i, &mut visited, &mut owner);
+    }
+    owner
+}
+
+/// Unordered multiset of values that are checked value-by-value.
+///
+/// Two sets match if each element of one matches a distinct element of the
+/// other, found by bipartite matching since `matches` may be looser than
+/// equality.
 #[derive(Debug, Clone, Default)]
 pub struct ValueSet<T>(pub Vec<T>);
 
-impl<'a, T> AbstractState for ValueSet<T>
+impl<T> ValueSet<T>
+where
+    T: AbstractState,
+{
+    /// Find a maximum one-to-one matching between elements of self and other.
+    ///
+    /// Return for each element of other the index of its matched element of self.
+    fn matching(&self, other: &Self) -> Vec<Option<usize>> {
+        max_matching(self.0.len(), other.0.len(), |i, j| {
+            self.0[i].matches(&other.0[j])
+        })
+    }
+
+    /// Indices of elements left unmatched by a maximum matching, of self and
+    /// of other.
+    fn unmatched_indices(&self, other: &Self) -> (Vec<usize>, Vec<usize>) {
+        let owner = self.matching(other);
+        let mut matched = vec![false; self.0.len()];
+        for i in owner.iter().flatten() {
+            matched[*i] = true;
+        }
+        let own = (0..self.0.len()).filter(|&i| !matched[i]).collect();
+        let others = (0..other.0.len()).filter(|&j| owner[j].is_none()).collect();
+        (own, others)
+    }
+
+    /// Elements left unmatched by a maximum matching, of self and of other.
+    pub fn unmatched<'a>(&'a self, other: &'a Self) -> (Vec<&'a T>, Vec<&'a T>) {
+        let (own, others) = self.unmatched_indices(other);
+        (
+            own.into_iter().map(|i| &self.0[i]).collect(),
+            others.into_iter().map(|j| &other.0[j]).collect(),
+        )
+    }
+}
+
+impl<T> AbstractState for ValueSet<T>
 where
     T: AbstractState + Clone,
 {
@@ -73,11 +184,43 @@ where
         if self.0.len() != other.0.len() {
             return false;
         }
-        self.0.iter().any(|a| other.0.iter().any(|b| a.matches(b)))
+        self.matching(other).iter().all(Option::is_some)
     }
     fn update(&mut self, other: &Self) {
         self.0 = other.0.clone();
     }
+    /// One path per unmatched element, `[-i]` for the element at index `i`
+    /// of self and `[+j]` for the element at index `j` of other.
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let (own, others) = self.unmatched_indices(other);
+        own.into_iter()
+            .map(|i| format!("[-{}]", i))
+            .chain(others.into_iter().map(|j| format!("[+{}]", j)))
+            .collect()
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (index, rest) = split_index(path)?;
+        // Elements only in other have no level in self
+        if index.starts_with('+') {
+            return None;
+        }
+        let index = index.strip_prefix('-').unwrap_or(index);
+        self.0.get(index.parse::<usize>().ok()?)?.level(rest)
+    }
+    /// Learn each element from the element of other it is matched with.
+    fn learn(&mut self, other: &Self) {
+        let owner = self.matching(other);
+        for (j, i) in owner.into_iter().enumerate() {
+            if let Some(i) = i {
+                self.0[i].learn(&other.0[j]);
+            }
+        }
+    }
+    /// Matched elements have the same key, so the key of a set doesn't
+    /// depend on their order.
+    fn key(&self) -> u64 {
+        unordered_key(self.0.iter().map(T::key))
+    }
 }
 
 impl<T> Deref for ValueSet<T> {
@@ -93,6 +236,10 @@ impl<T> DerefMut for ValueSet<T> {
 }
 
 /// Map of values. Keys are checked by equality.
+///
+/// Entries are identified by their position in the map in state paths, e.g.
+/// `[2].size`, with `[-i]` for a key only in self and `[+j]` for a key only
+/// in other. Use `BTreeMap` for paths with formatted keys.
 #[derive(Debug, Clone, Default)]
 pub struct ValueMap<K, V>(pub BTreeMap<K, V>)
 where
@@ -114,6 +261,45 @@ where
     fn update(&mut self, other: &Self) {
         self.0 = other.0.clone();
     }
+    fn diff(&self, other: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        for (i, (k, v)) in self.0.iter().enumerate() {
+            match other.0.get(k) {
+                Some(ov) => {
+                    let prefix = format!("[{}]", i);
+                    diffs.extend(v.diff(ov).iter().map(|p| join_path(&prefix, p)))
+                }
+                None => diffs.push(format!("[-{}]", i)),
+            }
+        }
+        for (j, k) in other.0.keys().enumerate() {
+            if !self.0.contains_key(k) {
+                diffs.push(format!("[+{}]", j));
+            }
+        }
+        diffs
+    }
+    fn level(&self, path: &str) -> Option<CheckLevel> {
+        let (index, rest) = split_index(path)?;
+        // Keys only in other have no level in self
+        if index.starts_with('+') {
+            return None;
+        }
+        let index = index.strip_prefix('-').unwrap_or(index);
+        self.0.values().nth(index.parse().ok()?)?.level(rest)
+    }
+    fn learn(&mut self, other: &Self) {
+        for (k, v) in self.0.iter_mut() {
+            if let Some(ov) = other.0.get(k) {
+                v.learn(ov);
+            }
+        }
+    }
+    /// Keys of the map are ordered and only match if equal, so the values
+    /// are hashed in order and the keys left out.
+    fn key(&self) -> u64 {
+        ordered_key(self.0.values().map(V::key))
+    }
 }
 
 impl<K, V> Deref for ValueMap<K, V>
@@ -133,3 +319,107 @@ where
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Element matching any value in its range, to check that matching is
+    /// one-to-one and not greedy.
+    #[derive(Clone)]
+    struct Range(u32, u32);
+
+    impl AbstractState for Range {
+        fn matches(&self, other: &Self) -> bool {
+            self.0.max(other.0) <= self.1.min(other.1)
+        }
+        fn update(&mut self, other: &Self) {
+            *self = other.clone();
+        }
+    }
+
+    /// Value whose level is its own.
+    #[derive(Clone)]
+    struct Leveled(CheckLevel);
+
+    impl AbstractState for Leveled {
+        fn matches(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+        fn update(&mut self, other: &Self) {
+            *self = other.clone();
+        }
+        fn level(&self, path: &str) -> Option<CheckLevel> {
+            path.is_empty().then_some(self.0)
+        }
+    }
+
+    #[test]
+    fn set_matching_is_one_to_one() {
+        let model = ValueSet(vec![Range(0, 10), Range(5, 5)]);
+        // A greedy matching pairs [0, 10] with [5, 5] and fails.
+        let target = ValueSet(vec![Range(5, 5), Range(1, 1)]);
+        assert!(target.matches(&model));
+        assert!(model.matches(&target));
+        let target = ValueSet(vec![Range(5, 5), Range(5, 5), Range(5, 5)]);
+        assert!(!target.matches(&model));
+    }
+
+    #[test]
+    fn set_diff_marks_sides() {
+        let model = ValueSet(vec![Value(1), Value(2), Value(2)]);
+        let target = ValueSet(vec![Value(2), Value(3), Value(1)]);
+        assert_eq!(model.diff(&target), vec!["[-2]", "[+1]"]);
+        let (own, others) = model.unmatched(&target);
+        assert_eq!(own.iter().map(|v| v.0).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(others.iter().map(|v| v.0).collect::<Vec<_>>(), vec![3]);
+        assert!(model
+            .diff(&ValueSet(vec![Value(2), Value(1), Value(2)]))
+            .is_empty());
+    }
+
+    #[test]
+    fn set_learns_matched_elements() {
+        use crate::state::Learned;
+        let mut model = ValueSet(vec![Learned::Known(Value(1)), Learned::Unknown]);
+        let target = ValueSet(vec![Learned::Known(Value(7)), Learned::Known(Value(1))]);
+        model.learn(&target);
+        assert_eq!(model[0].get().map(|v| v.0), Some(1));
+        assert_eq!(model[1].get().map(|v| v.0), Some(7));
+        assert!(target.matches(&model));
+        assert!(
+            !ValueSet(vec![Learned::Known(Value(8)), Learned::Known(Value(1))]).matches(&model)
+        );
+    }
+
+    #[test]
+    fn map_diff_uses_positions() {
+        let model = ValueMap(BTreeMap::from([
+            ("a", Value(1)),
+            ("b", Value(2)),
+            ("c", Value(3)),
+        ]));
+        let target = ValueMap(BTreeMap::from([
+            ("a", Value(1)),
+            ("b", Value(5)),
+            ("d", Value(3)),
+        ]));
+        assert_eq!(model.diff(&target), vec!["[1]", "[-2]", "[+2]"]);
+        assert!(!target.matches(&model));
+    }
+
+    #[test]
+    fn levels_of_own_elements_only() {
+        let set = ValueSet(vec![Leveled(CheckLevel::Strict), Leveled(CheckLevel::None)]);
+        assert_eq!(set.level("[1]"), Some(CheckLevel::None));
+        assert_eq!(set.level("[-0]"), Some(CheckLevel::Strict));
+        assert_eq!(set.level("[+0]"), None);
+        let map = ValueMap(BTreeMap::from([
+            (1, Leveled(CheckLevel::Relaxed)),
+            (2, Leveled(CheckLevel::Strict)),
+        ]));
+        assert_eq!(map.level("[1]"), Some(CheckLevel::Strict));
+        assert_eq!(map.level("[-0]"), Some(CheckLevel::Relaxed));
+        assert_eq!(map.level("[+0]"), None);
+    }
+}
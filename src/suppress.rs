@@ -0,0 +1,255 @@
+use crate::Error;
+use core::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// A mismatch between the model and the target after a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Name of the command.
+    pub command: String,
+    /// Return value of the model.
+    pub model_retv: isize,
+    /// Return value of the target.
+    pub target_retv: isize,
+    /// Paths of the differing parts of the state, empty if only the return
+    /// value differs.
+    pub paths: Vec<String>,
+}
+
+/// Signature of a known issue.
+///
+/// Each field is a pattern where `*` matches any sequence of characters. The
+/// return value pattern is matched against `model:target`, e.g. `-12:0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppression {
+    /// Command name pattern.
+    pub command: String,
+    /// Return value pair pattern.
+    pub retv: String,
+    /// State path pattern.
+    pub path: String,
+}
+
+impl Default for Suppression {
+    fn default() -> Self {
+        Self {
+            command: "*".to_string(),
+            retv: "*".to_string(),
+            path: "*".to_string(),
+        }
+    }
+}
+
+impl Suppression {
+    /// Parse a suppression from a line such as `command=mmap retv=-12:0 path=vmas*`.
+    ///
+    /// Omitted fields match anything.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut res = Self::default();
+        for item in line.split_whitespace() {
+            let (key, value) = item.split_once('=')?;
+            match key {
+                "command" => res.command = value.to_string(),
+                "retv" => res.retv = value.to_string(),
+                "path" => res.path = value.to_string(),
+                _ => return None,
+            }
+        }
+        Some(res)
+    }
+
+    /// Check if the command and return values of a mismatch match self.
+    fn matches_command(&self, mismatch: &Mismatch) -> bool {
+        let retv = format!("{}:{}", mismatch.model_retv, mismatch.target_retv);
+        glob_match(&self.command, &mismatch.command) && glob_match(&self.retv, &retv)
+    }
+
+    /// Check if a state path matches self.
+    fn matches_path(&self, path: &str) -> bool {
+        glob_match(&self.path, path)
+    }
+}
+
+impl Display for Suppression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "command={} retv={} path={}",
+            self.command, self.retv, self.path
+        )
+    }
+}
+
+/// Known issues whose mismatches are reported as warnings instead of errors.
+///
+/// A return value mismatch is suppressed by a rule matching its command and
+/// return values, with the default path pattern `*`. A state mismatch is
+/// suppressed if each differing path is matched by some rule.
+///
+/// The file format has one suppression per line, blank lines and lines
+/// starting with `#` are ignored:
+///
+/// ```text
+/// # mmap of a huge length succeeds on the target
+/// command=mmap retv=-12:* path=vmas*
+/// command=gettimeofday
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    /// Suppression rules.
+    rules: Vec<Suppression>,
+    /// Number of suppressed mismatches of each rule.
+    hits: Vec<usize>,
+    /// Total number of suppressed mismatches.
+    suppressed: usize,
+    /// Rules that suppressed a mismatch of the current command, if any did.
+    current: Option<Vec<usize>>,
+}
+
+impl Suppressions {
+    /// Create an empty set of suppressions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse suppressions, one per line.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut res = Self::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            res.push(Suppression::parse(line).ok_or(Error::Parse)?);
+        }
+        Ok(res)
+    }
+
+    /// Load suppressions from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path).map_err(|_| Error::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Add a suppression.
+    pub fn push(&mut self, suppression: Suppression) -> &mut Self {
+        self.rules.push(suppression);
+        self.hits.push(0);
+        self
+    }
+
+    /// Check if there is no suppression.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Start counting mismatches of a new command.
+    pub fn next_command(&mut self) {
+        self.current = None;
+    }
+
+    /// Check if a mismatch is a known issue, counting it if so.
+    ///
+    /// Mismatches of the same command, e.g. of its return value and of the
+    /// state, are counted once until `next_command`.
+    pub fn suppress(&mut self, mismatch: &Mismatch) -> bool {
+        let rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.matches_command(mismatch));
+        let mut hit = Vec::new();
+        if mismatch.paths.is_empty() {
+            match rules.clone().find(|(_, r)| r.path == "*") {
+                Some((i, _)) => hit.push(i),
+                None => return false,
+            }
+        }
+        for path in &mismatch.paths {
+            match rules.clone().find(|(_, r)| r.matches_path(path)) {
+                Some((i, _)) => hit.push(i),
+                None => return false,
+            }
+        }
+        hit.sort_unstable();
+        hit.dedup();
+        let current = match &mut self.current {
+            Some(current) => current,
+            None => {
+                self.suppressed += 1;
+                self.current.insert(Vec::new())
+            }
+        };
+        for i in hit {
+            if !current.contains(&i) {
+                current.push(i);
+                self.hits[i] += 1;
+            }
+        }
+        true
+    }
+
+    /// Iterate over suppressions and their number of suppressed mismatches.
+    pub fn hits(&self) -> impl Iterator<Item = (&Suppression, usize)> {
+        self.rules.iter().zip(self.hits.iter().copied())
+    }
+
+    /// Total number of suppressed mismatches.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+}
+
+/// Match a text against a pattern where `*` matches any sequence of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it matched up to.
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            // Let the last `*` match one more character.
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mismatch(paths: &[&str]) -> Mismatch {
+        Mismatch {
+            command: "mmap".to_string(),
+            model_retv: -12,
+            target_retv: 0,
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn counted_once_per_command() {
+        let text = "command=mmap path=vmas*\ncommand=mmap retv=-12:0";
+        let mut suppressions = Suppressions::parse(text).unwrap();
+        // Return value and state mismatches of the same command
+        assert!(suppressions.suppress(&mismatch(&[])));
+        assert!(suppressions.suppress(&mismatch(&["vmas[0]"])));
+        assert_eq!(suppressions.suppressed(), 1);
+        suppressions.next_command();
+        assert!(suppressions.suppress(&mismatch(&["vmas[1]", "vmas[2]"])));
+        assert_eq!(suppressions.suppressed(), 2);
+        let hits: Vec<usize> = suppressions.hits().map(|(_, n)| n).collect();
+        assert_eq!(hits, vec![2, 1]);
+    }
+}
@@ -0,0 +1,510 @@
+//! Behavior of the derive macros, as seen from a dependent crate.
+
+use km_checker::{Generate, Rng};
+
+mod generate {
+    use super::*;
+
+    const A: u32 = 0x1;
+    const B: u32 = 0x4;
+
+    #[derive(Debug, Generate)]
+    struct Args {
+        #[arg(range = 3..7)]
+        len: usize,
+        #[arg(flags = [A, B])]
+        flags: u32,
+        #[arg(with = fixed)]
+        fd: i32,
+    }
+
+    fn fixed(_rng: &mut Rng) -> i32 {
+        42
+    }
+
+    #[derive(Debug, Generate)]
+    enum Op {
+        Read(#[arg(range = 0..2)] u8),
+        Write {
+            #[arg(range = 1..4)]
+            len: u8,
+        },
+        Sync,
+    }
+
+    #[test]
+    fn fields_follow_their_strategy() {
+        let mut rng = Rng::new(1);
+        for _ in 0..200 {
+            let args = Args::generate(&mut rng);
+            assert!((3..7).contains(&args.len));
+            // At most one unknown bit
+            assert!((args.flags & !(A | B)).count_ones() <= 1);
+            assert_eq!(args.fd, 42);
+        }
+    }
+
+    #[test]
+    fn all_variants_are_generated() {
+        let mut rng = Rng::new(2);
+        let mut seen = [false; 3];
+        for _ in 0..200 {
+            match Op::generate(&mut rng) {
+                Op::Read(n) => {
+                    assert!(n < 2);
+                    seen[0] = true;
+                }
+                Op::Write { len } => {
+                    assert!((1..4).contains(&len));
+                    seen[1] = true;
+                }
+                Op::Sync => seen[2] = true,
+            }
+        }
+        assert_eq!(seen, [true; 3]);
+    }
+}
+
+mod generic_state {
+    use km_checker::state::{AbstractState, Value, ValueList, ValueMap};
+    use std::collections::BTreeMap;
+
+    /// Key that is not a state, only bound by what `ValueMap` needs.
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Key(u8);
+
+    mod table {
+        use km_checker::state::{ValueList, ValueMap};
+
+        // No import of `AbstractState`: the derive is fully qualified.
+        #[derive(Debug, Clone, km_checker::AbstractState)]
+        pub struct Table<T, K: Ord>
+        where
+            T: Clone,
+        {
+            pub rows: ValueList<T>,
+            pub index: ValueMap<K, T>,
+            pub count: u32,
+        }
+    }
+    use table::Table;
+
+    fn table(rows: &[u8], count: u32) -> Table<Value<u8>, Key> {
+        Table {
+            rows: ValueList(rows.iter().map(|&r| Value(r)).collect()),
+            index: ValueMap(BTreeMap::from([(Key(0), Value(rows[0]))])),
+            count,
+        }
+    }
+
+    #[test]
+    fn fields_depending_on_params_are_bound() {
+        let model = table(&[1, 2], 2);
+        assert!(table(&[1, 2], 2).matches(&model));
+        assert_eq!(model.diff(&table(&[1, 3], 3)), vec!["rows[1]", "count"]);
+        assert_eq!(model.diff(&table(&[4, 2], 2)), vec!["rows[0]", "index[0]"]);
+        let mut model = model;
+        model.update(&table(&[5, 6], 7));
+        assert!(table(&[5, 6], 7).matches(&model));
+    }
+}
+
+mod enum_state {
+    use km_checker::state::{AbstractState, Learned, Value};
+
+    #[derive(Debug, Clone, km_checker::AbstractState)]
+    enum Task {
+        Running { cpu: Value<u32>, pid: Learned<u32> },
+        Sleeping(Value<u64>),
+        Zombie,
+    }
+
+    fn running(cpu: u32) -> Task {
+        Task::Running {
+            cpu: Value(cpu),
+            pid: Learned::Unknown,
+        }
+    }
+
+    #[test]
+    fn variants_never_match() {
+        assert!(Task::Zombie.matches(&Task::Zombie));
+        assert!(!Task::Zombie.matches(&running(0)));
+        assert_eq!(Task::Sleeping(Value(1)).diff(&Task::Zombie), vec![""]);
+    }
+
+    #[test]
+    fn keys_of_variants() {
+        assert_eq!(running(0).key(), running(0).key());
+        assert_ne!(running(0).key(), running(1).key());
+        assert_ne!(Task::Sleeping(Value(0)).key(), Task::Zombie.key());
+    }
+
+    #[test]
+    fn fields_of_a_variant() {
+        assert_eq!(running(0).diff(&running(1)), vec!["cpu"]);
+        assert_eq!(
+            Task::Sleeping(Value(1)).diff(&Task::Sleeping(Value(2))),
+            vec!["0"]
+        );
+    }
+
+    #[test]
+    fn update_and_learn() {
+        let mut task = Task::Zombie;
+        task.update(&Task::Sleeping(Value(3)));
+        assert!(task.matches(&Task::Sleeping(Value(3))));
+        task.update(&running(1));
+        let target = Task::Running {
+            cpu: Value(1),
+            pid: Learned::Known(7),
+        };
+        task.learn(&target);
+        let Task::Running { pid, .. } = &task else {
+            panic!("variant of the target expected");
+        };
+        assert_eq!(pid.get(), Some(&7));
+        // Learning never changes the variant.
+        task.learn(&Task::Zombie);
+        assert!(task.matches(&target));
+    }
+}
+
+mod field_attrs {
+    use km_checker::state::{AbstractState, Learned, Value};
+    use km_checker::CheckLevel;
+
+    fn same_parity(a: &u32, b: &u32) -> bool {
+        a % 2 == b % 2
+    }
+
+    /// Not a state, compared with `compare_with`.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Opaque(u32);
+
+    fn same_opaque(a: &Opaque, b: &Opaque) -> bool {
+        a == b
+    }
+
+    #[derive(Debug, Clone, km_checker::AbstractState)]
+    struct Proc {
+        #[km(ignore)]
+        name: Value<String>,
+        #[km(no_update)]
+        pid: Learned<u32>,
+        #[km(no_compare)]
+        ticks: Value<u64>,
+        #[km(compare_with = same_parity)]
+        parity: u32,
+        #[km(compare_with = same_opaque, level = relaxed)]
+        opaque: Opaque,
+        #[km(tolerance = 10)]
+        time: u64,
+        #[km(level = none)]
+        cwd: Value<String>,
+        #[km(level = strict)]
+        fds: Option<Learned<u32>>,
+    }
+
+    fn proc() -> Proc {
+        Proc {
+            name: Value("init".to_string()),
+            pid: Learned::Known(1),
+            ticks: Value(0),
+            parity: 0,
+            opaque: Opaque(0),
+            time: 100,
+            cwd: Value("/".to_string()),
+            fds: Some(Learned::Unknown),
+        }
+    }
+
+    #[test]
+    fn ignored_and_uncompared_fields() {
+        let model = proc();
+        let mut target = proc();
+        target.name = Value("sh".to_string());
+        target.ticks = Value(5);
+        target.parity = 2;
+        target.time = 110;
+        assert!(target.matches(&model));
+        assert!(model.diff(&target).is_empty());
+        target.parity = 3;
+        target.time = 111;
+        assert_eq!(model.diff(&target), vec!["parity", "time"]);
+    }
+
+    #[test]
+    fn keys_of_matching_states() {
+        let model = proc();
+        let mut target = proc();
+        target.name = Value("sh".to_string());
+        target.pid = Learned::Unknown;
+        target.ticks = Value(5);
+        target.parity = 2;
+        target.time = 110;
+        target.fds = Some(Learned::Known(3));
+        assert!(target.matches(&model));
+        assert_eq!(target.key(), model.key());
+        target.cwd = Value("/tmp".to_string());
+        assert_ne!(target.key(), model.key());
+    }
+
+    #[test]
+    fn update_and_learn_skip_fields() {
+        let mut model = proc();
+        let mut target = proc();
+        target.name = Value("sh".to_string());
+        target.pid = Learned::Known(2);
+        target.ticks = Value(5);
+        target.fds = Some(Learned::Known(3));
+        model.learn(&target);
+        assert_eq!(model.pid.get(), Some(&1));
+        assert_eq!(model.fds.as_ref().and_then(|f| f.get()), Some(&3));
+        model.update(&target);
+        assert_eq!(model.name.0, "init");
+        assert_eq!(model.pid.get(), Some(&1));
+        assert_eq!(model.ticks.0, 5);
+    }
+
+    #[test]
+    fn field_levels() {
+        let model = proc();
+        let mut target = proc();
+        target.opaque = Opaque(1);
+        target.cwd = Value("/tmp".to_string());
+        assert_eq!(model.diff(&target), vec!["opaque", "cwd"]);
+        assert_eq!(model.level("opaque"), Some(CheckLevel::Relaxed));
+        assert_eq!(model.level("cwd"), Some(CheckLevel::None));
+        assert_eq!(model.level("fds"), Some(CheckLevel::Strict));
+        assert_eq!(model.level("time"), None);
+        // Uncompared fields have no level.
+        assert_eq!(model.level("ticks"), None);
+    }
+}
+
+mod command {
+    use km_checker::state::Value;
+    use km_checker::{Category, Command, CommandId, CommandRegistry, FromBytes, ID_LEN};
+
+    type S = Value<i64>;
+
+    #[derive(Debug, PartialEq, km_checker::Command)]
+    #[command(state = S, execute = run, id = 42, name = "write", category = fs)]
+    struct Write {
+        fd: i32,
+        data: Vec<u8>,
+        offset: Option<u64>,
+    }
+
+    impl Write {
+        fn run(&self, state: &mut S) -> isize {
+            state.0 += self.data.len() as i64;
+            self.data.len() as isize
+        }
+    }
+
+    #[derive(Debug, PartialEq, km_checker::Command)]
+    #[command(state = S, execute = run, read_only)]
+    struct Getpid;
+
+    impl Getpid {
+        fn run(&self, _state: &mut S) -> isize {
+            1
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let write = Write {
+            fd: 3,
+            data: vec![1, 2],
+            offset: None,
+        };
+        let cmd: &dyn Command<S> = &write;
+        assert_eq!(cmd.name(), "write");
+        assert_eq!(cmd.id(), Some(42));
+        assert_eq!(cmd.category(), Category::Fs);
+        assert!(!cmd.read_only());
+        let mut state = Value(0);
+        assert_eq!(cmd.execute(&mut state), 2);
+        assert_eq!(state.0, 2);
+        let cmd: &dyn Command<S> = &Getpid;
+        assert_eq!(cmd.name(), "Getpid");
+        assert_eq!(cmd.category(), Category::Other);
+        assert!(cmd.read_only());
+    }
+
+    #[test]
+    fn default_id_is_stable() {
+        // Recorded traces depend on it, see `km_checker::state::fnv1a`.
+        assert_eq!(Getpid::ID, 0xe2bc_aa32_327d_e1e0);
+        assert_eq!(<Getpid as CommandId>::ID, Getpid::ID);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let write = Write {
+            fd: -1,
+            data: vec![7; 3],
+            offset: Some(4096),
+        };
+        let bytes = Command::<S>::to_bytes(&write);
+        assert_eq!(bytes[..ID_LEN], 42u64.to_le_bytes());
+        assert_eq!(Write::from_bytes(&bytes[ID_LEN..]), Some(write));
+        // Trailing bytes are malformed.
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(Write::from_bytes(&longer[ID_LEN..]), None);
+        let mut registry = CommandRegistry::<S>::new();
+        registry
+            .register::<Write>()
+            .unwrap()
+            .register::<Getpid>()
+            .unwrap();
+        let decoded = registry.decode(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        let getpid = Command::<S>::to_bytes(&Getpid);
+        assert_eq!(registry.decode(&getpid).unwrap().name(), "Getpid");
+    }
+}
+
+mod command_set {
+    use km_checker::state::Value;
+    use km_checker::{Category, Command, CommandKind, CommandRegistry, Error};
+
+    type S = Value<i64>;
+
+    #[km_checker::command_set(state = S, execute = apply, category = fs)]
+    #[derive(Clone, PartialEq)]
+    enum Sys {
+        OpenAt {
+            path: String,
+            flags: u32,
+        },
+        #[command(id = 10)]
+        Close(i32),
+        #[command(category = proc, read_only)]
+        Sync,
+    }
+
+    impl Sys {
+        fn apply(&self, state: &mut S) -> isize {
+            if let Sys::Close(fd) = self {
+                state.0 = *fd as i64;
+            }
+            0
+        }
+    }
+
+    fn open() -> Sys {
+        Sys::OpenAt {
+            path: "/a".to_string(),
+            flags: 2,
+        }
+    }
+
+    #[test]
+    fn kinds_and_metadata() {
+        assert_eq!(
+            Sys::KINDS,
+            &[
+                CommandKind {
+                    id: 0,
+                    name: "open_at"
+                },
+                CommandKind {
+                    id: 10,
+                    name: "close"
+                },
+                CommandKind {
+                    id: 2,
+                    name: "sync"
+                },
+            ]
+        );
+        assert_eq!(Sys::Close(3).kind().name, "close");
+        let cmd: &dyn Command<S> = &open();
+        assert_eq!((cmd.name().as_str(), cmd.id()), ("open_at", Some(0)));
+        assert_eq!(cmd.category(), Category::Fs);
+        assert!(!cmd.read_only());
+        let cmd: &dyn Command<S> = &Sys::Sync;
+        assert_eq!(cmd.category(), Category::Proc);
+        assert!(cmd.read_only());
+        assert_eq!(format!("{:?}", open()), r#"open_at(path: "/a", flags: 2)"#);
+        assert_eq!(format!("{:?}", Sys::Close(3)), "close(3)");
+    }
+
+    #[test]
+    fn decode_and_register() {
+        for cmd in [open(), Sys::Close(-1), Sys::Sync] {
+            let bytes = Command::<S>::to_bytes(&cmd);
+            assert!(Sys::decode(&bytes) == Some(cmd));
+        }
+        assert!(Sys::decode(&[]).is_none());
+        let mut registry = CommandRegistry::<S>::new();
+        Sys::register(&mut registry).unwrap();
+        assert_eq!(registry.ids().collect::<Vec<_>>(), vec![0, 2, 10]);
+        let mut state = Value(0);
+        let close = registry
+            .decode(&Command::<S>::to_bytes(&Sys::Close(7)))
+            .unwrap();
+        close.execute(&mut state);
+        assert_eq!(state.0, 7);
+        assert_eq!(Sys::register(&mut registry), Err(Error::DuplicateCommand));
+    }
+}
+
+mod shrink {
+    use km_checker::Shrink;
+
+    #[derive(Debug, Clone, PartialEq, Shrink)]
+    struct Args {
+        len: u8,
+        #[shrink(skip)]
+        fd: i32,
+        sync: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shrink)]
+    enum Op {
+        Read(u8, #[shrink(skip)] u8),
+        Sync,
+    }
+
+    #[test]
+    fn one_field_at_a_time() {
+        let args = Args {
+            len: 4,
+            fd: 3,
+            sync: true,
+        };
+        let expected: Vec<Args> = 4u8
+            .shrink()
+            .into_iter()
+            .map(|len| Args {
+                len,
+                ..args.clone()
+            })
+            .chain(true.shrink().into_iter().map(|sync| Args {
+                sync,
+                ..args.clone()
+            }))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(args.shrink(), expected);
+    }
+
+    #[test]
+    fn variants_are_kept() {
+        let candidates = Op::Read(2, 9).shrink();
+        assert_eq!(
+            candidates,
+            2u8.shrink()
+                .into_iter()
+                .map(|n| Op::Read(n, 9))
+                .collect::<Vec<_>>()
+        );
+        assert!(Op::Sync.shrink().is_empty());
+    }
+}